@@ -0,0 +1,179 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use prismatic_color::{Color as P_Color, ColorSpace};
+
+use crate::camera::{ComparisonCamera, PrimaryCamera};
+use crate::color_models::{model_entry, ChannelInfo, PositionSpace};
+use crate::comparison::{ComparisonGizmos, ComparisonSettings};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{place_channels, place_color, VisualizationTarget, SCALE};
+
+const ANNOTATION_COLOR: Color = Color::srgba(0.6, 0.6, 0.6, 0.8);
+const LINE_SEGMENTS: usize = 48;
+
+// Text anchored in world space, collected each frame and painted over the scene by egui
+#[derive(Resource, Default)]
+pub struct WorldLabels {
+    // Position, text and whether the comparison camera of the split view shows it
+    labels: Vec<(Vec3, String, bool)>,
+}
+
+impl WorldLabels {
+    // A label painted by the camera that shows `target`
+    pub fn add(&mut self, target: &VisualizationTarget, position: Vec3, text: impl Into<String>) {
+        self.labels.push((position, text.into(), target.on_comparison_camera()));
+    }
+}
+
+pub fn clear_world_labels(mut labels: ResMut<WorldLabels>) {
+    labels.labels.clear();
+}
+
+pub fn paint_world_labels(
+    mut contexts: EguiContexts,
+    cameras: Query<(&Camera, &GlobalTransform, Has<ComparisonCamera>), Or<(With<PrimaryCamera>, With<ComparisonCamera>)>>,
+    labels: Res<WorldLabels>,
+) {
+    let Ok(ctx) = contexts.ctx_mut() else {return};
+
+    let painter = ctx.layer_painter(egui::LayerId::background());
+    for (camera, camera_transform, is_comparison) in &cameras {
+        if !camera.is_active {continue};
+        let Some(viewport) = camera.logical_viewport_rect() else {continue};
+        for (position, text, _) in labels.labels.iter().filter(|(_, _, on_comparison)| *on_comparison == is_comparison) {
+            let Ok(screen) = camera.world_to_viewport(camera_transform, *position) else {continue};
+            // Keep each side's labels on its own half of a split view
+            if !viewport.contains(screen) {continue};
+            painter.text(
+                egui::pos2(screen.x, screen.y),
+                egui::Align2::CENTER_CENTER,
+                text,
+                egui::FontId::proportional(12.),
+                egui::Color32::from_gray(200),
+            );
+        }
+    }
+}
+
+// Tick positions along a channel, every 30 degrees for a cyclic channel
+fn ticks(info: &ChannelInfo) -> Vec<f32> {
+    let divisions = if info.cyclic {12} else {4};
+    (0..=divisions)
+        .map(|tick| info.range.0 + info.span() * tick as f32 / divisions as f32)
+        .collect()
+}
+
+fn tick_label(info: &ChannelInfo, value: f32) -> String {
+    if info.cyclic {
+        format!("{:.0}°", (value - info.range.0) / info.span() * 360.)
+    } else {
+        format!("{:.2}", value)
+    }
+}
+
+// Draws axes, grid and value labels for each visualization on screen
+pub fn draw_annotations(
+    mut gizmos: Gizmos,
+    mut comparison_gizmos: Gizmos<ComparisonGizmos>,
+    mut labels: ResMut<WorldLabels>,
+    settings: Res<VisualizationSettings>,
+    comparison: Res<ComparisonSettings>,
+) {
+    annotate(&mut gizmos, &mut labels, &settings, &VisualizationTarget::primary());
+    if let Some((settings, target)) = comparison.shown() {
+        if target.on_comparison_camera() {
+            annotate(&mut comparison_gizmos, &mut labels, settings, &target);
+        } else {
+            annotate(&mut gizmos, &mut labels, settings, &target);
+        }
+    }
+}
+
+// Axes, grid and value labels for the channels of the color space model
+fn annotate<Config: GizmoConfigGroup>(
+    gizmos: &mut Gizmos<'_, '_, Config>,
+    labels: &mut WorldLabels,
+    settings: &VisualizationSettings,
+    target: &VisualizationTarget,
+) {
+    if !settings.show_annotations {return};
+
+    let cylindrical = settings.color_space == ColorSpace::Cylindrical;
+    let custom_model = settings.custom_model.as_deref().filter(|_| settings.position_space == Some(PositionSpace::Custom));
+    let (channels, polar) = match (settings.position_space, custom_model) {
+        (Some(PositionSpace::Perceptual(space)), _) => (space.channels(), space.is_polar() && cylindrical),
+        (_, Some(model)) => (model.channels(), model.channels()[0].cyclic && cylindrical),
+        _ => (model_entry(settings.color_space_model).channels, cylindrical),
+    };
+    let place = |channel_values: [f32; 3]| -> Vec3 {
+        let point = match (settings.position_space, custom_model) {
+            (Some(PositionSpace::Perceptual(space)), _) => space.place(channel_values, cylindrical),
+            (_, Some(_)) => place_channels(&channels, channel_values, cylindrical),
+            _ => place_color(P_Color::from_tuple((channel_values[0], channel_values[1], channel_values[2], 1.), settings.color_space_model), settings),
+        };
+        point * SCALE * settings.viz_scale + target.offset
+    };
+
+    // Straight lines in channel space, sampled so they follow curved spaces
+    let mut line = |from: [f32; 3], to: [f32; 3]| {
+        gizmos.linestrip(
+            (0..=LINE_SEGMENTS).map(|segment| {
+                let t = segment as f32 / LINE_SEGMENTS as f32;
+                place([0, 1, 2].map(|axis| from[axis] + (to[axis] - from[axis]) * t))
+            }),
+            ANNOTATION_COLOR,
+        );
+    };
+
+    let min = channels.map(|info| info.range.0);
+    let max = channels.map(|info| info.range.1);
+
+    if polar {
+        // Rings of constant radius and spokes of constant angle on the base
+        for radius in ticks(&channels[1]) {
+            line([min[0], radius, min[2]], [max[0], radius, min[2]]);
+        }
+        for angle in ticks(&channels[0]) {
+            line([angle, min[1], min[2]], [angle, max[1], min[2]]);
+        }
+        line([min[0], min[1], min[2]], [min[0], min[1], max[2]]);
+
+        for angle in ticks(&channels[0]).iter().take(12) {
+            labels.add(target, place([*angle, min[1] + channels[1].span() * 1.1, min[2]]), tick_label(&channels[0], *angle));
+        }
+        for radius in ticks(&channels[1]) {
+            labels.add(target, place([min[0], radius, min[2]]), tick_label(&channels[1], radius));
+        }
+        for height in ticks(&channels[2]) {
+            labels.add(target, place([min[0], min[1], height]), tick_label(&channels[2], height));
+        }
+
+        labels.add(target, place([min[0] + channels[0].span() / 24., min[1] + channels[1].span() * 1.25, min[2]]), channels[0].name);
+        labels.add(target, place([min[0] + channels[0].span() / 2., min[1] + channels[1].span() / 2., min[2]]), channels[1].name);
+    } else {
+        // Floor grid across the first two channels plus the rising third axis
+        for value in ticks(&channels[0]) {
+            line([value, min[1], min[2]], [value, max[1], min[2]]);
+        }
+        for value in ticks(&channels[1]) {
+            line([min[0], value, min[2]], [max[0], value, min[2]]);
+        }
+        line([min[0], min[1], min[2]], [min[0], min[1], max[2]]);
+
+        for value in ticks(&channels[0]) {
+            labels.add(target, place([value, min[1], min[2]]), tick_label(&channels[0], value));
+        }
+        for value in ticks(&channels[1]) {
+            labels.add(target, place([min[0], value, min[2]]), tick_label(&channels[1], value));
+        }
+        for value in ticks(&channels[2]) {
+            labels.add(target, place([min[0], min[1], value]), tick_label(&channels[2], value));
+        }
+
+        // Channel names just past the end of each axis
+        labels.add(target, place([max[0] + channels[0].span() * 0.15, min[1], min[2]]), channels[0].name);
+        labels.add(target, place([min[0], max[1] + channels[1].span() * 0.15, min[2]]), channels[1].name);
+    }
+
+    labels.add(target, place([min[0], min[1], max[2] + channels[2].span() * 0.1]), channels[2].name);
+}
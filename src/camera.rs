@@ -1,10 +1,37 @@
-use bevy::{input::mouse::{MouseButtonInput, MouseMotion}, prelude::{*}};
+use bevy::{input::mouse::{MouseButtonInput, MouseMotion}, prelude::{*}, render::view::NoIndirectDrawing};
+use bevy_pointcloud::render::PointCloudRenderMode;
+
+use crate::visualization::SCALE;
+
+// The camera driven by the keyboard controls
+#[derive(Component)]
+pub struct PrimaryCamera;
+
+// Second viewport of the split comparison view, follows the primary camera
+#[derive(Component)]
+pub struct ComparisonCamera;
+
+pub fn viewer_camera() -> impl Bundle {
+    (
+        Camera3d {..Default::default()},
+        Transform::from_xyz(SCALE*2., SCALE*2., SCALE*2.).looking_at(Vec3::new(0., 0., 0.), Vec3::Z),
+        NoIndirectDrawing,
+        Msaa::Off,
+        PointCloudRenderMode {
+            use_edl: false,
+            edl_radius: 2.8,
+            edl_strength: 0.4,
+            edl_neighbour_count: 4,
+            ..Default::default()
+        },
+    )
+}
 
 
 //Need to add default positions and rotation mode
 
 pub fn camera_controls(
-    mut camera_query: Query<&mut Transform, With<Camera>>,
+    mut camera_query: Query<&mut Transform, With<PrimaryCamera>>,
     keyboard: Res<ButtonInput<KeyCode>>,
     // mouse_button: Res<MouseButton>,
     // mouse_motion: Res<MouseMotion>,
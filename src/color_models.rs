@@ -0,0 +1,278 @@
+use bevy::math::Vec3;
+use prismatic_color::ColorModel;
+
+use crate::colorimetry::{opponent_to_polar, srgb_to_lab, srgb_to_luv, srgb_to_oklab};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorModelCategory {
+    Primary,
+    Spherical,
+    Cubic,
+    LumaChroma,
+    // A user defined model, see `custom_model`
+    Custom,
+}
+
+impl ColorModelCategory {
+    pub const ALL: [ColorModelCategory; 5] = [
+        ColorModelCategory::Primary,
+        ColorModelCategory::Spherical,
+        ColorModelCategory::Cubic,
+        ColorModelCategory::LumaChroma,
+        ColorModelCategory::Custom,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorModelCategory::Primary => "Primary",
+            ColorModelCategory::Spherical => "Spherical",
+            ColorModelCategory::Cubic => "Cubic",
+            ColorModelCategory::LumaChroma => "Luma-Chroma",
+            ColorModelCategory::Custom => "Custom",
+        }
+    }
+
+    pub fn models(self) -> impl Iterator<Item = &'static ColorModelEntry> {
+        COLOR_MODELS.iter().filter(move |entry| entry.category == self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelInfo {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub range: (f32, f32),
+    pub cyclic: bool,
+}
+
+impl ChannelInfo {
+    const fn linear(name: &'static str, min: f32, max: f32) -> Self {
+        Self { name, unit: "", range: (min, max), cyclic: false }
+    }
+
+    const fn hue() -> Self {
+        Self { name: "Hue", unit: "turn", range: (0., 1.), cyclic: true }
+    }
+
+    pub fn label(&self) -> String {
+        if self.unit.is_empty() {
+            self.name.to_string()
+        } else {
+            format!("{} ({})", self.name, self.unit)
+        }
+    }
+
+    pub fn span(&self) -> f32 {
+        self.range.1 - self.range.0
+    }
+}
+
+// Alpha is shared by every model and sampled separately from the lattice channels
+pub const ALPHA_CHANNEL: ChannelInfo = ChannelInfo::linear("Alpha", 0., 1.);
+
+// One row per color model the visualizer knows how to sample
+pub struct ColorModelEntry {
+    pub model: ColorModel,
+    pub category: ColorModelCategory,
+    pub name: &'static str,
+    pub channels: [ChannelInfo; 3],
+    // Components addressed by the perceptual offset and gamma controls
+    pub primaries: [&'static str; 3],
+}
+
+pub const RGB_PRIMARIES: [&str; 3] = ["Red", "Green", "Blue"];
+
+pub const COLOR_MODELS: &[ColorModelEntry] = &[
+    ColorModelEntry {
+        model: ColorModel::RGBA,
+        category: ColorModelCategory::Primary,
+        name: "RGB",
+        channels: [
+            ChannelInfo::linear("Red", 0., 1.),
+            ChannelInfo::linear("Green", 0., 1.),
+            ChannelInfo::linear("Blue", 0., 1.),
+        ],
+        primaries: RGB_PRIMARIES,
+    },
+    ColorModelEntry {
+        model: ColorModel::CMYA,
+        category: ColorModelCategory::Primary,
+        name: "CMY",
+        channels: [
+            ChannelInfo::linear("Cyan", 0., 1.),
+            ChannelInfo::linear("Magenta", 0., 1.),
+            ChannelInfo::linear("Yellow", 0., 1.),
+        ],
+        // The offset and gamma work on the RGB components, not on the inks
+        primaries: RGB_PRIMARIES,
+    },
+    ColorModelEntry {
+        model: ColorModel::SphericalHCLA,
+        category: ColorModelCategory::Spherical,
+        name: "HCL",
+        channels: [
+            ChannelInfo::hue(),
+            ChannelInfo::linear("Chroma", 0., 1.),
+            ChannelInfo::linear("Lightness", 0., 1.),
+        ],
+        primaries: RGB_PRIMARIES,
+    },
+    ColorModelEntry {
+        model: ColorModel::CubicHSVA,
+        category: ColorModelCategory::Cubic,
+        name: "HSV",
+        channels: [
+            ChannelInfo::hue(),
+            ChannelInfo::linear("Saturation", 0., 1.),
+            ChannelInfo::linear("Value", 0., 1.),
+        ],
+        primaries: RGB_PRIMARIES,
+    },
+    ColorModelEntry {
+        model: ColorModel::CubicHSLA,
+        category: ColorModelCategory::Cubic,
+        name: "HSL",
+        channels: [
+            ChannelInfo::hue(),
+            ChannelInfo::linear("Saturation", 0., 1.),
+            ChannelInfo::linear("Lightness", 0., 1.),
+        ],
+        primaries: RGB_PRIMARIES,
+    },
+    ColorModelEntry {
+        model: ColorModel::YUVA,
+        category: ColorModelCategory::LumaChroma,
+        name: "YUV",
+        // Chroma difference channels are centered on the neutral axis
+        channels: [
+            ChannelInfo::linear("Luma", 0., 1.),
+            ChannelInfo::linear("U", -0.5, 0.5),
+            ChannelInfo::linear("V", -0.5, 0.5),
+        ],
+        primaries: RGB_PRIMARIES,
+    },
+];
+
+pub fn model_entry(model: ColorModel) -> &'static ColorModelEntry {
+    COLOR_MODELS
+        .iter()
+        .find(|entry| entry.model == model)
+        .unwrap_or(&COLOR_MODELS[0])
+}
+
+// Spaces that take over positioning from the color space model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PositionSpace {
+    Perceptual(PerceptualSpace),
+    // The compiled custom color model, through its inverse
+    Custom,
+}
+
+// Perceptual reference spaces colors can be positioned by, computed here rather than by prismatic_color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PerceptualSpace {
+    CieLab,
+    CieLchAb,
+    CieLuv,
+    CieLchUv,
+    OkLab,
+    OkLch,
+}
+
+impl PerceptualSpace {
+    pub const ALL: [PerceptualSpace; 6] = [
+        PerceptualSpace::CieLab,
+        PerceptualSpace::CieLchAb,
+        PerceptualSpace::CieLuv,
+        PerceptualSpace::CieLchUv,
+        PerceptualSpace::OkLab,
+        PerceptualSpace::OkLch,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PerceptualSpace::CieLab => "CIELAB",
+            PerceptualSpace::CieLchAb => "LCh(ab)",
+            PerceptualSpace::CieLuv => "CIELUV",
+            PerceptualSpace::CieLchUv => "LCh(uv)",
+            PerceptualSpace::OkLab => "OKLab",
+            PerceptualSpace::OkLch => "OKLCh",
+        }
+    }
+
+    // Hue, chroma, lightness spaces that roll up into a cylinder
+    pub fn is_polar(&self) -> bool {
+        matches!(self, PerceptualSpace::CieLchAb | PerceptualSpace::CieLchUv | PerceptualSpace::OkLch)
+    }
+
+    fn lightness_max(&self) -> f32 {
+        match self {
+            PerceptualSpace::OkLab | PerceptualSpace::OkLch => 1.,
+            _ => 100.,
+        }
+    }
+
+    // Chroma mapped to the edge of the unit cube, a little past the most saturated sRGB primary
+    fn chroma_reference(&self) -> f32 {
+        match self {
+            PerceptualSpace::CieLab | PerceptualSpace::CieLchAb => 150.,
+            PerceptualSpace::CieLuv | PerceptualSpace::CieLchUv => 180.,
+            PerceptualSpace::OkLab | PerceptualSpace::OkLch => 0.33,
+        }
+    }
+
+    // Ordered like the model channels, with lightness last so it rises along Z
+    pub fn channels(&self) -> [ChannelInfo; 3] {
+        let (lightness, chroma) = (self.lightness_max(), self.chroma_reference());
+        match self {
+            PerceptualSpace::CieLab | PerceptualSpace::OkLab => [
+                ChannelInfo::linear("a", -chroma, chroma),
+                ChannelInfo::linear("b", -chroma, chroma),
+                ChannelInfo::linear("L", 0., lightness),
+            ],
+            PerceptualSpace::CieLuv => [
+                ChannelInfo::linear("u", -chroma, chroma),
+                ChannelInfo::linear("v", -chroma, chroma),
+                ChannelInfo::linear("L", 0., lightness),
+            ],
+            _ => [
+                ChannelInfo::hue(),
+                ChannelInfo::linear("Chroma", 0., chroma),
+                ChannelInfo::linear("L", 0., lightness),
+            ],
+        }
+    }
+
+    // Channel values of an sRGB color in this space
+    pub fn coordinates(&self, srgb: [f32; 3]) -> [f32; 3] {
+        let opponent = match self {
+            PerceptualSpace::CieLab | PerceptualSpace::CieLchAb => srgb_to_lab(srgb),
+            PerceptualSpace::CieLuv | PerceptualSpace::CieLchUv => srgb_to_luv(srgb),
+            PerceptualSpace::OkLab | PerceptualSpace::OkLch => srgb_to_oklab(srgb),
+        };
+        if self.is_polar() {
+            let (hue, chroma) = opponent_to_polar(opponent.y, opponent.z);
+            [hue, chroma, opponent.x]
+        } else {
+            [opponent.y, opponent.z, opponent.x]
+        }
+    }
+
+    // Model space position inside the unit cube, the neutral axis through its center.
+    // Polar spaces unroll into a box unless `cylindrical`, opponent spaces are always Cartesian.
+    pub fn place(&self, coordinates: [f32; 3], cylindrical: bool) -> Vec3 {
+        let radius = |chroma: f32| chroma / (2. * self.chroma_reference());
+        let height = coordinates[2] / self.lightness_max();
+        if !self.is_polar() {
+            return Vec3::new(0.5 + radius(coordinates[0]), 0.5 + radius(coordinates[1]), height);
+        }
+
+        let [hue, chroma, _] = coordinates;
+        if cylindrical {
+            let angle = hue * std::f32::consts::TAU;
+            Vec3::new(0.5 + radius(chroma) * angle.cos(), 0.5 + radius(chroma) * angle.sin(), height)
+        } else {
+            Vec3::new(hue, chroma / self.chroma_reference(), height)
+        }
+    }
+}
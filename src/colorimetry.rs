@@ -0,0 +1,319 @@
+// Published constants are kept digit for digit as printed, beyond what f32 holds
+#![allow(clippy::excessive_precision)]
+
+use bevy::math::{Mat3, Vec3};
+
+// Linear sRGB (D65) to CIE XYZ, columns are the XYZ of each primary
+pub const LINEAR_SRGB_TO_XYZ: Mat3 = Mat3::from_cols_array(&[
+    0.4124564, 0.2126729, 0.0193339,
+    0.3575761, 0.7151522, 0.1191920,
+    0.1804375, 0.0721750, 0.9503041,
+]);
+
+pub const XYZ_TO_LINEAR_SRGB: Mat3 = Mat3::from_cols_array(&[
+    3.2404542, -0.9692660, 0.0556434,
+    -1.5371385, 1.8760108, -0.2040259,
+    -0.4985314, 0.0415560, 1.0572252,
+]);
+
+// sRGB transfer functions, mirrored around zero so out of gamut values survive the round trip
+pub fn srgb_to_linear(encoded: f32) -> f32 {
+    let magnitude = encoded.abs();
+    let linear = if magnitude <= 0.04045 {
+        magnitude / 12.92
+    } else {
+        ((magnitude + 0.055) / 1.055).powf(2.4)
+    };
+    linear.copysign(encoded)
+}
+
+pub fn linear_to_srgb(linear: f32) -> f32 {
+    let magnitude = linear.abs();
+    let encoded = if magnitude <= 0.0031308 {
+        magnitude * 12.92
+    } else {
+        1.055 * magnitude.powf(1. / 2.4) - 0.055
+    };
+    encoded.copysign(linear)
+}
+
+pub fn srgb_to_xyz(srgb: [f32; 3]) -> Vec3 {
+    LINEAR_SRGB_TO_XYZ * Vec3::from(srgb.map(srgb_to_linear))
+}
+
+pub fn xyz_to_srgb(xyz: Vec3) -> [f32; 3] {
+    (XYZ_TO_LINEAR_SRGB * xyz).to_array().map(linear_to_srgb)
+}
+
+// Chromaticity coordinates and luminance
+pub fn xyz_to_xyy(xyz: Vec3) -> Vec3 {
+    let sum = xyz.x + xyz.y + xyz.z;
+    if sum <= 0. {
+        return Vec3::ZERO;
+    }
+    Vec3::new(xyz.x / sum, xyz.y / sum, xyz.y)
+}
+
+// D65 reference white for CIELAB
+const D65_WHITE: Vec3 = Vec3::new(0.95047, 1.0, 1.08883);
+
+pub fn xyz_to_lab(xyz: Vec3) -> Vec3 {
+    let f = |t: f32| {
+        let delta: f32 = 6. / 29.;
+        if t > delta.powi(3) {t.cbrt()} else {t / (3. * delta * delta) + 4. / 29.}
+    };
+    let [fx, fy, fz] = (xyz / D65_WHITE).to_array().map(f);
+    Vec3::new(116. * fy - 16., 500. * (fx - fy), 200. * (fy - fz))
+}
+
+pub fn srgb_to_lab(srgb: [f32; 3]) -> Vec3 {
+    xyz_to_lab(srgb_to_xyz(srgb))
+}
+
+// CIELUV against the same D65 white, L* shared with CIELAB
+pub fn xyz_to_luv(xyz: Vec3) -> Vec3 {
+    let chromaticity = |xyz: Vec3| {
+        let denominator = xyz.x + 15. * xyz.y + 3. * xyz.z;
+        if denominator <= 0. {(0., 0.)} else {(4. * xyz.x / denominator, 9. * xyz.y / denominator)}
+    };
+    let lightness = xyz_to_lab(xyz).x;
+    let (u, v) = chromaticity(xyz);
+    let (u_white, v_white) = chromaticity(D65_WHITE);
+    Vec3::new(lightness, 13. * lightness * (u - u_white), 13. * lightness * (v - v_white))
+}
+
+pub fn srgb_to_luv(srgb: [f32; 3]) -> Vec3 {
+    xyz_to_luv(srgb_to_xyz(srgb))
+}
+
+// Hue in turns and chroma of an opponent pair such as a*b* or u*v*
+pub fn opponent_to_polar(a: f32, b: f32) -> (f32, f32) {
+    let hue = if a == 0. && b == 0. {0.} else {(b.atan2(a) / std::f32::consts::TAU).rem_euclid(1.)};
+    (hue, a.hypot(b))
+}
+
+pub fn linear_srgb_to_oklab(linear: Vec3) -> Vec3 {
+    let l = 0.4122214708 * linear.x + 0.5363325363 * linear.y + 0.0514459929 * linear.z;
+    let m = 0.2119034982 * linear.x + 0.6806995451 * linear.y + 0.1073969566 * linear.z;
+    let s = 0.0883024619 * linear.x + 0.2817188376 * linear.y + 0.6299787005 * linear.z;
+    let (l, m, s) = (l.cbrt(), m.cbrt(), s.cbrt());
+    Vec3::new(
+        0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+        1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+        0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+    )
+}
+
+pub fn srgb_to_oklab(srgb: [f32; 3]) -> Vec3 {
+    linear_srgb_to_oklab(Vec3::from(srgb.map(srgb_to_linear)))
+}
+
+pub fn oklab_to_linear_srgb(oklab: Vec3) -> Vec3 {
+    let l = oklab.x + 0.3963377774 * oklab.y + 0.2158037573 * oklab.z;
+    let m = oklab.x - 0.1055613458 * oklab.y - 0.0638541728 * oklab.z;
+    let s = oklab.x - 0.0894841775 * oklab.y - 1.2914855480 * oklab.z;
+    let (l, m, s) = (l * l * l, m * m * m, s * s * s);
+    Vec3::new(
+        4.0767416621 * l - 3.3077115913 * m + 0.2309699292 * s,
+        -1.2684380046 * l + 2.6097574011 * m - 0.3413193965 * s,
+        -0.0041960863 * l - 0.7034186147 * m + 1.7076147010 * s,
+    )
+}
+
+pub fn oklab_to_srgb(oklab: Vec3) -> [f32; 3] {
+    oklab_to_linear_srgb(oklab).to_array().map(linear_to_srgb)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeltaEMetric {
+    Cie76,
+    Ciede2000,
+    OkLab,
+}
+
+impl DeltaEMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeltaEMetric::Cie76 => "ΔE76",
+            DeltaEMetric::Ciede2000 => "ΔE2000",
+            DeltaEMetric::OkLab => "ΔEok",
+        }
+    }
+
+    // Perceptual distance between two sRGB colors
+    pub fn delta_e(&self, srgb_1: [f32; 3], srgb_2: [f32; 3]) -> f32 {
+        match self {
+            DeltaEMetric::Cie76 => srgb_to_lab(srgb_1).distance(srgb_to_lab(srgb_2)),
+            DeltaEMetric::Ciede2000 => ciede2000(srgb_to_lab(srgb_1), srgb_to_lab(srgb_2)),
+            DeltaEMetric::OkLab => srgb_to_oklab(srgb_1).distance(srgb_to_oklab(srgb_2)),
+        }
+    }
+}
+
+// CIEDE2000 color difference between two CIELAB colors, with unit weighting factors
+pub fn ciede2000(lab_1: Vec3, lab_2: Vec3) -> f32 {
+    let (l1, a1, b1) = (lab_1.x, lab_1.y, lab_1.z);
+    let (l2, a2, b2) = (lab_2.x, lab_2.y, lab_2.z);
+    let pow7 = |x: f32| x.powi(7);
+    let twenty_five_pow7 = pow7(25.);
+
+    let c_bar = ((a1 * a1 + b1 * b1).sqrt() + (a2 * a2 + b2 * b2).sqrt()) / 2.;
+    let g = 0.5 * (1. - (pow7(c_bar) / (pow7(c_bar) + twenty_five_pow7)).sqrt());
+
+    let a1_prime = (1. + g) * a1;
+    let a2_prime = (1. + g) * a2;
+    let c1_prime = (a1_prime * a1_prime + b1 * b1).sqrt();
+    let c2_prime = (a2_prime * a2_prime + b2 * b2).sqrt();
+    let hue = |b: f32, a: f32| if b == 0. && a == 0. {0.} else {b.atan2(a).to_degrees().rem_euclid(360.)};
+    let h1_prime = hue(b1, a1_prime);
+    let h2_prime = hue(b2, a2_prime);
+
+    let delta_l = l2 - l1;
+    let delta_c = c2_prime - c1_prime;
+    let chroma_product = c1_prime * c2_prime;
+    let delta_h_angle = if chroma_product == 0. {
+        0.
+    } else {
+        let difference = h2_prime - h1_prime;
+        if difference.abs() <= 180. {
+            difference
+        } else if difference > 180. {
+            difference - 360.
+        } else {
+            difference + 360.
+        }
+    };
+    let delta_h = 2. * chroma_product.sqrt() * (delta_h_angle.to_radians() / 2.).sin();
+
+    let l_bar = (l1 + l2) / 2.;
+    let c_bar_prime = (c1_prime + c2_prime) / 2.;
+    let h_bar = if chroma_product == 0. {
+        h1_prime + h2_prime
+    } else if (h1_prime - h2_prime).abs() <= 180. {
+        (h1_prime + h2_prime) / 2.
+    } else if h1_prime + h2_prime < 360. {
+        (h1_prime + h2_prime + 360.) / 2.
+    } else {
+        (h1_prime + h2_prime - 360.) / 2.
+    };
+
+    let cos_deg = |degrees: f32| degrees.to_radians().cos();
+    let t = 1. - 0.17 * cos_deg(h_bar - 30.) + 0.24 * cos_deg(2. * h_bar) + 0.32 * cos_deg(3. * h_bar + 6.)
+        - 0.20 * cos_deg(4. * h_bar - 63.);
+    let delta_theta = 30. * (-((h_bar - 275.) / 25.).powi(2)).exp();
+    let r_c = 2. * (pow7(c_bar_prime) / (pow7(c_bar_prime) + twenty_five_pow7)).sqrt();
+    let s_l = 1. + 0.015 * (l_bar - 50.).powi(2) / (20. + (l_bar - 50.).powi(2)).sqrt();
+    let s_c = 1. + 0.045 * c_bar_prime;
+    let s_h = 1. + 0.015 * c_bar_prime * t;
+    let r_t = -(2. * delta_theta).to_radians().sin() * r_c;
+
+    let (l_term, c_term, h_term) = (delta_l / s_l, delta_c / s_c, delta_h / s_h);
+    (l_term * l_term + c_term * c_term + h_term * h_term + r_t * c_term * h_term).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec3, expected: [f32; 3], tolerance: f32) {
+        for axis in 0..3 {
+            assert!((actual[axis] - expected[axis]).abs() <= tolerance, "{actual:?} differs from {expected:?}");
+        }
+    }
+
+    // Inverse of `xyz_to_lab`, only needed to check the forward conversion round trips
+    fn lab_to_xyz(lab: Vec3) -> Vec3 {
+        let f_inverse = |t: f32| {
+            let delta: f32 = 6. / 29.;
+            if t > delta {t.powi(3)} else {3. * delta * delta * (t - 4. / 29.)}
+        };
+        let fy = (lab.x + 16.) / 116.;
+        Vec3::new(f_inverse(fy + lab.y / 500.), f_inverse(fy), f_inverse(fy - lab.z / 200.)) * D65_WHITE
+    }
+
+    // Sharma, Wu and Dalal, "The CIEDE2000 Color-Difference Formula: Implementation Notes,
+    // Supplementary Test Data, and Mathematical Observations", table 1
+    const SHARMA_PAIRS: [([f32; 3], [f32; 3], f32); 34] = [
+        ([50.0000, 2.6772, -79.7751], [50.0000, 0.0000, -82.7485], 2.0425),
+        ([50.0000, 3.1571, -77.2803], [50.0000, 0.0000, -82.7485], 2.8615),
+        ([50.0000, 2.8361, -74.0200], [50.0000, 0.0000, -82.7485], 3.4412),
+        ([50.0000, -1.3802, -84.2814], [50.0000, 0.0000, -82.7485], 1.0000),
+        ([50.0000, -1.1848, -84.8006], [50.0000, 0.0000, -82.7485], 1.0000),
+        ([50.0000, -0.9009, -85.5211], [50.0000, 0.0000, -82.7485], 1.0000),
+        ([50.0000, 0.0000, 0.0000], [50.0000, -1.0000, 2.0000], 2.3669),
+        ([50.0000, -1.0000, 2.0000], [50.0000, 0.0000, 0.0000], 2.3669),
+        ([50.0000, 2.4900, -0.0010], [50.0000, -2.4900, 0.0009], 7.1792),
+        ([50.0000, 2.4900, -0.0010], [50.0000, -2.4900, 0.0010], 7.1792),
+        ([50.0000, 2.4900, -0.0010], [50.0000, -2.4900, 0.0011], 7.2195),
+        ([50.0000, 2.4900, -0.0010], [50.0000, -2.4900, 0.0012], 7.2195),
+        ([50.0000, -0.0010, 2.4900], [50.0000, 0.0009, -2.4900], 4.8045),
+        ([50.0000, -0.0010, 2.4900], [50.0000, 0.0010, -2.4900], 4.8045),
+        ([50.0000, -0.0010, 2.4900], [50.0000, 0.0011, -2.4900], 4.7461),
+        ([50.0000, 2.5000, 0.0000], [50.0000, 0.0000, -2.5000], 4.3065),
+        ([50.0000, 2.5000, 0.0000], [73.0000, 25.0000, -18.0000], 27.1492),
+        ([50.0000, 2.5000, 0.0000], [61.0000, -5.0000, 29.0000], 22.8977),
+        ([50.0000, 2.5000, 0.0000], [56.0000, -27.0000, -3.0000], 31.9030),
+        ([50.0000, 2.5000, 0.0000], [58.0000, 24.0000, 15.0000], 19.4535),
+        ([50.0000, 2.5000, 0.0000], [50.0000, 3.1736, 0.5854], 1.0000),
+        ([50.0000, 2.5000, 0.0000], [50.0000, 3.2972, 0.0000], 1.0000),
+        ([50.0000, 2.5000, 0.0000], [50.0000, 1.8634, 0.5757], 1.0000),
+        ([50.0000, 2.5000, 0.0000], [50.0000, 3.2592, 0.3350], 1.0000),
+        ([60.2574, -34.0099, 36.2677], [60.4626, -34.1751, 39.4387], 1.2644),
+        ([63.0109, -31.0961, -5.8663], [62.8187, -29.7946, -4.0864], 1.2630),
+        ([61.2901, 3.7196, -5.3901], [61.4292, 2.2480, -4.9620], 1.8731),
+        ([35.0831, -44.1164, 3.7933], [35.0232, -40.0716, 1.5901], 1.8645),
+        ([22.7233, 20.0904, -46.6940], [23.0331, 14.9730, -42.5619], 2.0373),
+        ([36.4612, 47.8580, 18.3852], [36.2715, 50.5065, 21.2231], 1.4146),
+        ([90.8027, -2.0831, 1.4410], [91.1528, -1.6435, 0.0447], 1.4441),
+        ([90.9257, -0.5406, -0.9208], [88.6381, -0.8985, -0.7239], 1.5381),
+        ([6.7747, -0.2908, -2.4247], [5.8714, -0.0985, -2.2286], 0.6377),
+        ([2.0776, 0.0795, -1.1350], [0.9033, -0.0636, -0.5514], 0.9082),
+    ];
+
+    #[test]
+    fn ciede2000_matches_sharma_test_data() {
+        for (lab_1, lab_2, expected) in SHARMA_PAIRS {
+            let difference = ciede2000(Vec3::from(lab_1), Vec3::from(lab_2));
+            assert!((difference - expected).abs() < 1e-3, "{lab_1:?} {lab_2:?}: {difference} instead of {expected}");
+            let reversed = ciede2000(Vec3::from(lab_2), Vec3::from(lab_1));
+            assert!((reversed - difference).abs() < 1e-4, "{lab_1:?} {lab_2:?} isn't symmetric");
+        }
+    }
+
+    #[test]
+    fn lab_of_srgb_primaries() {
+        assert_close(srgb_to_lab([1., 1., 1.]), [100., 0., 0.], 0.01);
+        assert_close(srgb_to_lab([1., 0., 0.]), [53.2408, 80.0925, 67.2032], 0.01);
+        assert_close(srgb_to_lab([0., 1., 0.]), [87.7347, -86.1827, 83.1793], 0.01);
+        assert_close(srgb_to_lab([0., 0., 1.]), [32.2970, 79.1875, -107.8602], 0.01);
+    }
+
+    #[test]
+    fn lab_round_trips() {
+        for srgb in [[1., 1., 1.], [0.5, 0.5, 0.5], [1., 0., 0.], [0.2, 0.6, 0.9], [0.01, 0.02, 0.005]] {
+            let round_trip = xyz_to_srgb(lab_to_xyz(srgb_to_lab(srgb)));
+            assert_close(Vec3::from(round_trip), srgb, 1e-4);
+        }
+    }
+
+    #[test]
+    fn luv_of_srgb_red() {
+        assert_close(srgb_to_luv([1., 0., 0.]), [53.2408, 175.0151, 37.7564], 0.01);
+        assert_close(srgb_to_luv([1., 1., 1.]), [100., 0., 0.], 0.01);
+    }
+
+    #[test]
+    fn oklab_of_srgb_primaries() {
+        assert_close(srgb_to_oklab([1., 1., 1.]), [1., 0., 0.], 1e-4);
+        assert_close(srgb_to_oklab([1., 0., 0.]), [0.627955, 0.224863, 0.125846], 1e-4);
+        assert_close(srgb_to_oklab([0., 1., 0.]), [0.866440, -0.233888, 0.179498], 1e-4);
+        assert_close(srgb_to_oklab([0., 0., 1.]), [0.452014, -0.032457, -0.311528], 1e-4);
+    }
+
+    #[test]
+    fn oklab_round_trips() {
+        for srgb in [[1., 1., 1.], [0.5, 0.5, 0.5], [1., 0., 0.], [0.2, 0.6, 0.9], [0.01, 0.02, 0.005]] {
+            assert_close(Vec3::from(oklab_to_srgb(srgb_to_oklab(srgb))), srgb, 1e-4);
+        }
+    }
+}
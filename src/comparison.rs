@@ -0,0 +1,143 @@
+use bevy::{prelude::*, render::camera::Viewport, render::view::RenderLayers, window::PrimaryWindow};
+use bevy_pointcloud::point_cloud::PointCloud;
+use bevy_pointcloud::point_cloud_material::PointCloudMaterial;
+
+use crate::camera::{ComparisonCamera, PrimaryCamera};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{spawn_3d_visualization, VisualizationMesh, VisualizationSlot, VisualizationTarget, SCALE};
+
+// Render layer seen only by the comparison camera in split view
+pub const COMPARISON_LAYER: usize = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComparisonMode {
+    Off,
+    // Two viewports side by side with linked cameras
+    Split,
+    // Both visualizations in one view, the second shifted along X
+    Overlay,
+}
+
+// Immediate mode gizmos drawn only for the comparison camera of the split view
+#[derive(Default, Reflect, GizmoConfigGroup)]
+pub struct ComparisonGizmos;
+
+// A second settings set rendered next to the primary one
+#[derive(Resource, Clone, PartialEq)]
+pub struct ComparisonSettings {
+    pub mode: ComparisonMode,
+    pub overlay_offset: f32,
+    pub settings: VisualizationSettings,
+}
+
+impl ComparisonSettings {
+    pub fn new(settings: VisualizationSettings) -> Self {
+        Self {
+            mode: ComparisonMode::Off,
+            overlay_offset: 2.5,
+            settings,
+        }
+    }
+
+    // The comparison settings and where their visualization goes, while comparing
+    pub fn shown(&self) -> Option<(&VisualizationSettings, VisualizationTarget)> {
+        (self.mode != ComparisonMode::Off).then(|| (&self.settings, self.target()))
+    }
+
+    fn target(&self) -> VisualizationTarget {
+        match self.mode {
+            ComparisonMode::Overlay => VisualizationTarget {
+                slot: VisualizationSlot::Comparison,
+                layers: RenderLayers::default(),
+                offset: Vec3::X * self.overlay_offset * SCALE * self.settings.viz_scale,
+            },
+            _ => VisualizationTarget {
+                slot: VisualizationSlot::Comparison,
+                layers: RenderLayers::layer(COMPARISON_LAYER),
+                offset: Vec3::ZERO,
+            },
+        }
+    }
+}
+
+pub fn update_comparison_visualization(
+    mut commands: Commands,
+    comparison: Res<ComparisonSettings>,
+    meshes: ResMut<Assets<Mesh>>,
+    materials: ResMut<Assets<StandardMaterial>>,
+    point_clouds: ResMut<Assets<PointCloud>>,
+    point_cloud_materials: ResMut<Assets<PointCloudMaterial>>,
+    gizmo_assets: ResMut<Assets<GizmoAsset>>,
+    entities: Query<(Entity, &VisualizationMesh)>,
+) {
+    if !comparison.is_changed() {return};
+
+    for (entity, mesh) in &entities {
+        if mesh.0 == VisualizationSlot::Comparison {
+            commands.entity(entity).despawn();
+        }
+    }
+
+    if comparison.mode != ComparisonMode::Off {
+        spawn_3d_visualization(commands, meshes, materials, point_clouds, point_cloud_materials, gizmo_assets, &comparison.settings, &comparison.target());
+    }
+}
+
+// Splits the window between the two cameras while the split view is on
+pub fn update_comparison_viewports(
+    comparison: Res<ComparisonSettings>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    mut primary: Query<&mut Camera, (With<PrimaryCamera>, Without<ComparisonCamera>)>,
+    mut secondary: Query<&mut Camera, (With<ComparisonCamera>, Without<PrimaryCamera>)>,
+) {
+    let Ok(window) = windows.single() else {return};
+    let split = comparison.mode == ComparisonMode::Split;
+    let half = UVec2::new(window.physical_width() / 2, window.physical_height());
+    if half.x == 0 || half.y == 0 {return};
+
+    let left = split.then(|| Viewport {
+        physical_position: UVec2::ZERO,
+        physical_size: half,
+        ..default()
+    });
+    let right = split.then(|| Viewport {
+        physical_position: UVec2::new(half.x, 0),
+        physical_size: half,
+        ..default()
+    });
+
+    // Only touch the cameras when the layout actually changes, so they aren't flagged as changed every frame
+    for mut camera in &mut primary {
+        if !same_viewport(&camera.viewport, &left) {
+            camera.viewport = left.clone();
+        }
+    }
+
+    for mut camera in &mut secondary {
+        if camera.is_active != split {
+            camera.is_active = split;
+        }
+        if !same_viewport(&camera.viewport, &right) {
+            camera.viewport = right.clone();
+        }
+    }
+}
+
+fn same_viewport(a: &Option<Viewport>, b: &Option<Viewport>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.physical_position == b.physical_position && a.physical_size == b.physical_size,
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// The comparison camera follows every move of the primary one
+pub fn sync_comparison_camera(
+    primary: Query<&Transform, (With<PrimaryCamera>, Without<ComparisonCamera>)>,
+    mut secondary: Query<&mut Transform, (With<ComparisonCamera>, Without<PrimaryCamera>)>,
+) {
+    let Ok(primary) = primary.single() else {return};
+    for mut transform in &mut secondary {
+        *transform = *primary;
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+
+use crate::colorimetry::{linear_to_srgb, srgb_to_linear};
+
+// Relative luminance as defined by WCAG 2
+pub fn relative_luminance(srgb: [f32; 3]) -> f32 {
+    let [r, g, b] = srgb.map(|channel| srgb_to_linear(channel.clamp(0., 1.)));
+    0.2126 * r + 0.7152 * g + 0.0722 * b
+}
+
+pub fn wcag_contrast_ratio(srgb_1: [f32; 3], srgb_2: [f32; 3]) -> f32 {
+    let (l1, l2) = (relative_luminance(srgb_1), relative_luminance(srgb_2));
+    (l1.max(l2) + 0.05) / (l1.min(l2) + 0.05)
+}
+
+// APCA 0.0.98G lightness contrast Lc of text on a background, negative for light text on dark
+pub fn apca_contrast(text: [f32; 3], background: [f32; 3]) -> f32 {
+    const BLACK_THRESHOLD: f32 = 0.022;
+    const BLACK_CLAMP: f32 = 1.414;
+    const SCALE: f32 = 1.14;
+    const LOW_OFFSET: f32 = 0.027;
+    const LOW_CLIP: f32 = 0.1;
+    const DELTA_Y_MIN: f32 = 0.0005;
+
+    let screen_luminance = |srgb: [f32; 3]| {
+        let [r, g, b] = srgb.map(|channel| channel.clamp(0., 1.).powf(2.4));
+        let y = 0.2126729 * r + 0.7151522 * g + 0.072175 * b;
+        if y < BLACK_THRESHOLD {y + (BLACK_THRESHOLD - y).powf(BLACK_CLAMP)} else {y}
+    };
+    let (text_y, background_y) = (screen_luminance(text), screen_luminance(background));
+    if (background_y - text_y).abs() < DELTA_Y_MIN {
+        return 0.;
+    }
+
+    let output = if background_y > text_y {
+        let sapc = (background_y.powf(0.56) - text_y.powf(0.57)) * SCALE;
+        if sapc < LOW_CLIP {0.} else {sapc - LOW_OFFSET}
+    } else {
+        let sapc = (background_y.powf(0.65) - text_y.powf(0.62)) * SCALE;
+        if sapc > -LOW_CLIP {0.} else {sapc + LOW_OFFSET}
+    };
+    output * 100.
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContrastMetric {
+    Wcag2,
+    Apca,
+}
+
+impl ContrastMetric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContrastMetric::Wcag2 => "WCAG 2",
+            ContrastMetric::Apca => "APCA",
+        }
+    }
+
+    // Ascending pass levels, contrast ratios for WCAG and absolute Lc for APCA
+    pub fn thresholds(&self) -> [f32; 3] {
+        match self {
+            ContrastMetric::Wcag2 => [3., 4.5, 7.],
+            ContrastMetric::Apca => [45., 60., 75.],
+        }
+    }
+
+    pub fn threshold_label(&self, threshold: f32) -> String {
+        match self {
+            ContrastMetric::Wcag2 => format!("{threshold}:1"),
+            ContrastMetric::Apca => format!("Lc {threshold}"),
+        }
+    }
+}
+
+// Contrast of every sample, as text, against a reference background color
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContrastFilter {
+    pub metric: ContrastMetric,
+    // Linear RGB, as edited by the color picker
+    pub reference: [f32; 3],
+    // Index into the metric's thresholds a sample has to reach to pass
+    pub level: usize,
+    pub hide_failing: bool,
+}
+
+impl Default for ContrastFilter {
+    fn default() -> Self {
+        Self {
+            metric: ContrastMetric::Wcag2,
+            reference: [1., 1., 1.],
+            level: 1,
+            hide_failing: false,
+        }
+    }
+}
+
+impl ContrastFilter {
+    pub fn reference_srgb(&self) -> [f32; 3] {
+        self.reference.map(linear_to_srgb)
+    }
+
+    pub fn contrast(&self, srgb: [f32; 3]) -> f32 {
+        match self.metric {
+            ContrastMetric::Wcag2 => wcag_contrast_ratio(srgb, self.reference_srgb()),
+            ContrastMetric::Apca => apca_contrast(srgb, self.reference_srgb()).abs(),
+        }
+    }
+
+    // How many of the metric's thresholds the sample reaches, 0 to 3
+    pub fn band(&self, srgb: [f32; 3]) -> usize {
+        let contrast = self.contrast(srgb);
+        self.metric.thresholds().iter().filter(|threshold| contrast >= **threshold).count()
+    }
+
+    pub fn passes(&self, srgb: [f32; 3]) -> bool {
+        self.band(srgb) > self.level
+    }
+
+    // Failing samples red, then orange, yellow green and green for each level reached
+    pub fn band_color(&self, srgb: [f32; 3]) -> Color {
+        match self.band(srgb) {
+            0 => Color::srgb(0.85, 0.15, 0.15),
+            1 => Color::srgb(0.95, 0.55, 0.1),
+            2 => Color::srgb(0.75, 0.85, 0.2),
+            _ => Color::srgb(0.15, 0.7, 0.3),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: [f32; 3] = [1., 1., 1.];
+    const BLACK: [f32; 3] = [0., 0., 0.];
+
+    fn gray(level: u8) -> [f32; 3] {
+        [level as f32 / 255.; 3]
+    }
+
+    #[test]
+    fn wcag_extremes_and_symmetry() {
+        assert!((wcag_contrast_ratio(BLACK, WHITE) - 21.).abs() < 1e-4);
+        assert!((wcag_contrast_ratio(WHITE, WHITE) - 1.).abs() < 1e-6);
+        assert_eq!(wcag_contrast_ratio(gray(0x88), WHITE), wcag_contrast_ratio(WHITE, gray(0x88)));
+    }
+
+    #[test]
+    fn apca_matches_reference_values() {
+        // #888 text on white and the reverse, from the APCA 0.0.98G reference calculator
+        assert!((apca_contrast(gray(0x88), WHITE) - 63.06).abs() < 0.1);
+        assert!((apca_contrast(WHITE, gray(0x88)) + 68.54).abs() < 0.1);
+        assert!((apca_contrast(BLACK, WHITE) - 106.04).abs() < 0.1);
+        assert_eq!(apca_contrast(WHITE, WHITE), 0.);
+    }
+
+    #[test]
+    fn level_one_is_the_four_and_a_half_to_one_threshold() {
+        let filter = ContrastFilter { level: 1, ..Default::default() };
+        assert_eq!(filter.metric.thresholds()[filter.level], 4.5);
+        // #767676 is the lightest gray reaching 4.5:1 on white, #777777 falls just short
+        assert!(wcag_contrast_ratio(gray(0x76), WHITE) >= 4.5);
+        assert!(wcag_contrast_ratio(gray(0x77), WHITE) < 4.5);
+        assert!(filter.passes(gray(0x76)));
+        assert!(!filter.passes(gray(0x77)));
+    }
+}
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use crate::color_models::ChannelInfo;
+use crate::expression::Expression;
+
+// A color model defined outside prismatic_color, sampled and positioned like the built in ones
+pub trait CustomColorModel: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn channels(&self) -> [ChannelInfo; 3];
+
+    // Channel values to linear sRGB
+    fn to_linear_rgb(&self, channels: [f32; 3]) -> [f32; 3];
+
+    // Linear sRGB back to channel values, used to position colors by this model
+    fn from_linear_rgb(&self, rgb: [f32; 3]) -> [f32; 3];
+}
+
+// Models are compared by identity, a recompiled model counts as a new one
+impl PartialEq for dyn CustomColorModel {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+const CHANNEL_VARIABLES: [&str; 3] = ["x", "y", "z"];
+const RGB_VARIABLES: [&str; 3] = ["r", "g", "b"];
+
+// Expression text as typed in the settings panel
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionSource {
+    // r, g and b in terms of the channels x, y and z
+    pub forward: [String; 3],
+    // x, y and z in terms of linear r, g and b
+    pub inverse: [String; 3],
+    // Whether x is a hue that wraps around once per turn
+    pub cyclic_x: bool,
+    // Outcome of the last compile, shown under the editor
+    pub status: Option<String>,
+}
+
+impl Default for ExpressionSource {
+    // A plain gamma 2.2 encoding of RGB to start from
+    fn default() -> Self {
+        Self {
+            forward: ["x^2.2", "y^2.2", "z^2.2"].map(String::from),
+            inverse: ["r^(1/2.2)", "g^(1/2.2)", "b^(1/2.2)"].map(String::from),
+            cyclic_x: false,
+            status: None,
+        }
+    }
+}
+
+impl ExpressionSource {
+    // Parses the six expressions, recording the outcome in `status`
+    pub fn compile(&mut self) -> Option<Arc<dyn CustomColorModel>> {
+        match ExpressionModel::parse(self) {
+            Ok(model) => {
+                self.status = Some("Compiled".to_string());
+                Some(Arc::new(model))
+            },
+            Err(error) => {
+                self.status = Some(error);
+                None
+            },
+        }
+    }
+}
+
+pub struct ExpressionModel {
+    forward: [Expression; 3],
+    inverse: [Expression; 3],
+    cyclic_x: bool,
+}
+
+impl ExpressionModel {
+    pub fn parse(source: &ExpressionSource) -> Result<Self, String> {
+        let parse_all = |sources: &[String; 3], outputs: [&str; 3], variables: &[&str]| -> Result<[Expression; 3], String> {
+            let [first, second, third] = [0, 1, 2].map(|index| {
+                Expression::parse(&sources[index], variables).map_err(|error| format!("{}: {error}", outputs[index]))
+            });
+            Ok([first?, second?, third?])
+        };
+
+        Ok(Self {
+            forward: parse_all(&source.forward, RGB_VARIABLES, &CHANNEL_VARIABLES)?,
+            inverse: parse_all(&source.inverse, CHANNEL_VARIABLES, &RGB_VARIABLES)?,
+            cyclic_x: source.cyclic_x,
+        })
+    }
+}
+
+impl CustomColorModel for ExpressionModel {
+    fn name(&self) -> &str {
+        "Expression"
+    }
+
+    fn channels(&self) -> [ChannelInfo; 3] {
+        let linear = |name| ChannelInfo { name, unit: "", range: (0., 1.), cyclic: false };
+        [
+            ChannelInfo { cyclic: self.cyclic_x, unit: if self.cyclic_x {"turn"} else {""}, ..linear("x") },
+            linear("y"),
+            linear("z"),
+        ]
+    }
+
+    fn to_linear_rgb(&self, channels: [f32; 3]) -> [f32; 3] {
+        self.forward.each_ref().map(|expression| expression.evaluate(&channels))
+    }
+
+    fn from_linear_rgb(&self, rgb: [f32; 3]) -> [f32; 3] {
+        self.inverse.each_ref().map(|expression| expression.evaluate(&rgb))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_source_round_trips_gamma_2_2() {
+        let model = ExpressionModel::parse(&ExpressionSource::default()).unwrap();
+        let rgb = model.to_linear_rgb([0.5, 0.25, 1.]);
+        let expected = [0.5f32.powf(2.2), 0.25f32.powf(2.2), 1.];
+        for (value, expected) in rgb.iter().zip(expected) {
+            assert!((value - expected).abs() < 1e-6, "{rgb:?}");
+        }
+        let channels = model.from_linear_rgb(rgb);
+        for (value, expected) in channels.iter().zip([0.5, 0.25, 1.]) {
+            assert!((value - expected).abs() < 1e-5, "{channels:?}");
+        }
+    }
+
+    #[test]
+    fn compile_reports_the_failing_output() {
+        let mut source = ExpressionSource::default();
+        source.forward[1] = "y^".to_string();
+        assert!(source.compile().is_none());
+        assert!(source.status.as_deref().is_some_and(|status| status.starts_with("g:")), "{:?}", source.status);
+    }
+}
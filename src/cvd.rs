@@ -0,0 +1,184 @@
+use bevy::prelude::*;
+use prismatic_color::{Color as P_Color, ColorModel};
+
+use crate::colorimetry::{linear_to_srgb, srgb_to_linear};
+
+// Machado et al. 2009 matrices on linear sRGB for severities 0, 0.1, ... 1, rows applied to (r, g, b)
+const MACHADO_PROTAN: [[[f32; 3]; 3]; 11] = [
+    [[1.000000, 0.000000, 0.000000], [0.000000, 1.000000, 0.000000], [0.000000, 0.000000, 1.000000]],
+    [[0.856167, 0.182038, -0.038205], [0.029342, 0.955115, 0.015544], [-0.002880, -0.001563, 1.004443]],
+    [[0.734766, 0.334872, -0.069637], [0.051840, 0.919198, 0.028963], [-0.004928, -0.004209, 1.009137]],
+    [[0.630323, 0.465641, -0.095964], [0.069181, 0.890046, 0.040773], [-0.006308, -0.007724, 1.014032]],
+    [[0.539009, 0.579343, -0.118352], [0.082546, 0.866121, 0.051332], [-0.007136, -0.011959, 1.019095]],
+    [[0.458064, 0.679578, -0.137642], [0.092785, 0.846313, 0.060902], [-0.007494, -0.016807, 1.024301]],
+    [[0.385450, 0.769005, -0.154455], [0.100526, 0.829802, 0.069673], [-0.007442, -0.022190, 1.029632]],
+    [[0.319627, 0.849633, -0.169261], [0.106241, 0.815969, 0.077790], [-0.007025, -0.028051, 1.035076]],
+    [[0.259411, 0.923008, -0.182420], [0.110296, 0.804340, 0.085364], [-0.006276, -0.034346, 1.040622]],
+    [[0.203876, 0.990338, -0.194214], [0.112975, 0.794542, 0.092483], [-0.005222, -0.041043, 1.046265]],
+    [[0.152286, 1.052583, -0.204868], [0.114503, 0.786281, 0.099216], [-0.003882, -0.048116, 1.051998]],
+];
+const MACHADO_DEUTAN: [[[f32; 3]; 3]; 11] = [
+    [[1.000000, 0.000000, 0.000000], [0.000000, 1.000000, 0.000000], [0.000000, 0.000000, 1.000000]],
+    [[0.866435, 0.177704, -0.044139], [0.049567, 0.939063, 0.011370], [-0.003453, 0.007233, 0.996220]],
+    [[0.760729, 0.319078, -0.079807], [0.090568, 0.889315, 0.020117], [-0.006027, 0.013325, 0.992702]],
+    [[0.675425, 0.433850, -0.109275], [0.125303, 0.847755, 0.026942], [-0.007950, 0.018572, 0.989378]],
+    [[0.605511, 0.528560, -0.134071], [0.155318, 0.812366, 0.032316], [-0.009376, 0.023176, 0.986200]],
+    [[0.547494, 0.607765, -0.155259], [0.181692, 0.781742, 0.036566], [-0.010410, 0.027275, 0.983136]],
+    [[0.498864, 0.674741, -0.173604], [0.205199, 0.754872, 0.039929], [-0.011131, 0.030969, 0.980162]],
+    [[0.457771, 0.731899, -0.189670], [0.226409, 0.731012, 0.042579], [-0.011595, 0.034333, 0.977261]],
+    [[0.422823, 0.781057, -0.203881], [0.245752, 0.709602, 0.044646], [-0.011843, 0.037423, 0.974421]],
+    [[0.392952, 0.823610, -0.216562], [0.263559, 0.690210, 0.046232], [-0.011910, 0.040281, 0.971630]],
+    [[0.367322, 0.860646, -0.227968], [0.280085, 0.672501, 0.047413], [-0.011820, 0.042940, 0.968881]],
+];
+const MACHADO_TRITAN: [[[f32; 3]; 3]; 11] = [
+    [[1.000000, 0.000000, 0.000000], [0.000000, 1.000000, 0.000000], [0.000000, 0.000000, 1.000000]],
+    [[0.926670, 0.092514, -0.019184], [0.021191, 0.964503, 0.014306], [0.008437, 0.054813, 0.936750]],
+    [[0.895720, 0.133330, -0.029050], [0.029997, 0.945400, 0.024603], [0.013027, 0.104707, 0.882266]],
+    [[0.905871, 0.127791, -0.033662], [0.026856, 0.941251, 0.031893], [0.013410, 0.148296, 0.838294]],
+    [[0.948035, 0.089490, -0.037526], [0.014364, 0.946792, 0.038844], [0.010853, 0.193991, 0.795156]],
+    [[1.017277, 0.027029, -0.044306], [-0.006113, 0.958479, 0.047634], [0.006379, 0.248708, 0.744913]],
+    [[1.104996, -0.046633, -0.058363], [-0.032137, 0.971635, 0.060503], [0.001336, 0.317922, 0.680742]],
+    [[1.193214, -0.109812, -0.083402], [-0.058496, 0.979410, 0.079086], [-0.002346, 0.403492, 0.598854]],
+    [[1.257728, -0.139648, -0.118081], [-0.078003, 0.975409, 0.102594], [-0.003316, 0.501214, 0.502102]],
+    [[1.278864, -0.125333, -0.153531], [-0.084748, 0.957674, 0.127074], [-0.000989, 0.601151, 0.399838]],
+    [[1.255528, -0.076749, -0.178779], [-0.078411, 0.930809, 0.147602], [0.004733, 0.691367, 0.303900]],
+];
+
+// Brettel et al. 1997 projections onto two half planes, on linear sRGB
+struct BrettelPlanes {
+    separation: [f32; 3],
+    first: [[f32; 3]; 3],
+    second: [[f32; 3]; 3],
+}
+
+const BRETTEL_PROTAN: BrettelPlanes = BrettelPlanes {
+    separation: [0.00048, 0.00393, -0.00441],
+    first: [[0.14980, 1.19548, -0.34528], [0.10764, 0.84864, 0.04372], [0.00384, -0.00540, 1.00156]],
+    second: [[0.14570, 1.16172, -0.30742], [0.10816, 0.85291, 0.03892], [0.00386, -0.00524, 1.00139]],
+};
+const BRETTEL_DEUTAN: BrettelPlanes = BrettelPlanes {
+    separation: [-0.00281, -0.00611, 0.00892],
+    first: [[0.36477, 0.86381, -0.22858], [0.26294, 0.64245, 0.09462], [-0.02006, 0.02728, 0.99278]],
+    second: [[0.37298, 0.88166, -0.25464], [0.25954, 0.63506, 0.10540], [-0.01980, 0.02784, 0.99196]],
+};
+const BRETTEL_TRITAN: BrettelPlanes = BrettelPlanes {
+    separation: [0.03901, -0.02788, -0.01113],
+    first: [[1.01277, 0.13548, -0.14826], [-0.01243, 0.86812, 0.14431], [0.07589, 0.80500, 0.11911]],
+    second: [[0.93678, 0.18979, -0.12657], [0.06154, 0.81526, 0.12320], [-0.37562, 1.12767, 0.24796]],
+};
+
+fn apply(rows: &[[f32; 3]; 3], linear: Vec3) -> Vec3 {
+    Vec3::new(
+        Vec3::from(rows[0]).dot(linear),
+        Vec3::from(rows[1]).dot(linear),
+        Vec3::from(rows[2]).dot(linear),
+    )
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Deficiency {
+    None,
+    Protan,
+    Deutan,
+    Tritan,
+}
+
+impl Deficiency {
+    pub const ALL: [Deficiency; 4] = [Deficiency::None, Deficiency::Protan, Deficiency::Deutan, Deficiency::Tritan];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Deficiency::None => "Off",
+            Deficiency::Protan => "Protan",
+            Deficiency::Deutan => "Deutan",
+            Deficiency::Tritan => "Tritan",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CvdModel {
+    Machado,
+    Brettel,
+}
+
+// Color vision deficiency simulation applied to what is drawn, and optionally where
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CvdFilter {
+    pub deficiency: Deficiency,
+    pub model: CvdModel,
+    // 0 is normal vision, 1 full dichromacy
+    pub severity: f32,
+    pub affect_positions: bool,
+}
+
+impl Default for CvdFilter {
+    fn default() -> Self {
+        Self {
+            deficiency: Deficiency::None,
+            model: CvdModel::Machado,
+            severity: 1.,
+            affect_positions: false,
+        }
+    }
+}
+
+impl CvdFilter {
+    pub fn is_active(&self) -> bool {
+        self.deficiency != Deficiency::None && self.severity > 0.
+    }
+
+    pub fn simulate_linear(&self, linear: Vec3) -> Vec3 {
+        if !self.is_active() {
+            return linear;
+        }
+        match self.model {
+            CvdModel::Machado => {
+                let matrices = match self.deficiency {
+                    Deficiency::Protan => &MACHADO_PROTAN,
+                    Deficiency::Deutan => &MACHADO_DEUTAN,
+                    _ => &MACHADO_TRITAN,
+                };
+                // Between the two tabulated severities either side
+                let position = self.severity.clamp(0., 1.) * (matrices.len() - 1) as f32;
+                let index = (position.floor() as usize).min(matrices.len() - 2);
+                let fraction = position - index as f32;
+                apply(&matrices[index], linear).lerp(apply(&matrices[index + 1], linear), fraction)
+            },
+            CvdModel::Brettel => {
+                let planes = match self.deficiency {
+                    Deficiency::Protan => &BRETTEL_PROTAN,
+                    Deficiency::Deutan => &BRETTEL_DEUTAN,
+                    _ => &BRETTEL_TRITAN,
+                };
+                let side = Vec3::from(planes.separation).dot(linear);
+                let dichromat = apply(if side >= 0. {&planes.first} else {&planes.second}, linear);
+                // Brettel only models dichromacy, so partial severities blend towards it
+                linear.lerp(dichromat, self.severity.clamp(0., 1.))
+            },
+        }
+    }
+
+    pub fn simulate_srgb(&self, srgb: [f32; 3]) -> [f32; 3] {
+        if !self.is_active() {
+            return srgb;
+        }
+        self.simulate_linear(Vec3::from(srgb.map(srgb_to_linear))).to_array().map(linear_to_srgb)
+    }
+
+    pub fn simulate_bevy_color(&self, color: Color) -> Color {
+        if !self.is_active() {
+            return color;
+        }
+        let srgba = color.to_srgba();
+        let [r, g, b] = self.simulate_srgb([srgba.red, srgba.green, srgba.blue]);
+        Color::srgba(r, g, b, srgba.alpha)
+    }
+
+    // The simulated color as an RGB model color, for placing it in the visualization space
+    pub fn simulate_color(&self, color: P_Color) -> P_Color {
+        let [r, g, b, a] = color.to_rgb().to_array();
+        let [r, g, b] = self.simulate_srgb([r, g, b]);
+        P_Color::from_tuple((r, g, b, a), ColorModel::RGBA)
+    }
+}
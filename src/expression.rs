@@ -0,0 +1,359 @@
+// A small arithmetic language for user defined color models:
+// numbers, named variables, + - * / ^, parentheses and a handful of math functions.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Function {
+    Sin,
+    Cos,
+    Tan,
+    Atan2,
+    Abs,
+    Sqrt,
+    Cbrt,
+    Exp,
+    Ln,
+    Pow,
+    Min,
+    Max,
+    Clamp,
+    Floor,
+    Fract,
+    Mix,
+}
+
+impl Function {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "sin" => Function::Sin,
+            "cos" => Function::Cos,
+            "tan" => Function::Tan,
+            "atan2" => Function::Atan2,
+            "abs" => Function::Abs,
+            "sqrt" => Function::Sqrt,
+            "cbrt" => Function::Cbrt,
+            "exp" => Function::Exp,
+            "ln" => Function::Ln,
+            "pow" => Function::Pow,
+            "min" => Function::Min,
+            "max" => Function::Max,
+            "clamp" => Function::Clamp,
+            "floor" => Function::Floor,
+            "fract" => Function::Fract,
+            "mix" => Function::Mix,
+            _ => return None,
+        })
+    }
+
+    fn arity(&self) -> usize {
+        match self {
+            Function::Atan2 | Function::Pow | Function::Min | Function::Max => 2,
+            Function::Clamp | Function::Mix => 3,
+            _ => 1,
+        }
+    }
+
+    fn apply(&self, arguments: &[f32]) -> f32 {
+        match self {
+            Function::Sin => arguments[0].sin(),
+            Function::Cos => arguments[0].cos(),
+            Function::Tan => arguments[0].tan(),
+            Function::Atan2 => arguments[0].atan2(arguments[1]),
+            Function::Abs => arguments[0].abs(),
+            Function::Sqrt => arguments[0].sqrt(),
+            Function::Cbrt => arguments[0].cbrt(),
+            Function::Exp => arguments[0].exp(),
+            Function::Ln => arguments[0].ln(),
+            Function::Pow => signed_pow(arguments[0], arguments[1]),
+            Function::Min => arguments[0].min(arguments[1]),
+            Function::Max => arguments[0].max(arguments[1]),
+            Function::Clamp => arguments[0].clamp(arguments[1].min(arguments[2]), arguments[2].max(arguments[1])),
+            Function::Floor => arguments[0].floor(),
+            Function::Fract => arguments[0].rem_euclid(1.),
+            Function::Mix => arguments[0] + (arguments[1] - arguments[0]) * arguments[2],
+        }
+    }
+}
+
+// Powers of negative bases keep their sign so transfer curves stay defined below zero
+fn signed_pow(base: f32, exponent: f32) -> f32 {
+    if base < 0. && exponent.fract() != 0. {
+        -(-base).powf(exponent)
+    } else {
+        base.powf(exponent)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Number(f32),
+    Variable(usize),
+    Negate(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+    Call(Function, Vec<Node>),
+}
+
+// A parsed expression over a fixed list of variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    root: Node,
+}
+
+impl Expression {
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, String> {
+        let mut parser = Parser { chars: source.chars().collect(), position: 0, variables };
+        let root = parser.expression()?;
+        parser.skip_whitespace();
+        if parser.position < parser.chars.len() {
+            return Err(format!("unexpected '{}' at {}", parser.chars[parser.position], parser.position + 1));
+        }
+        Ok(Self { root })
+    }
+
+    // `values` are given in the order of the variables the expression was parsed with
+    pub fn evaluate(&self, values: &[f32]) -> f32 {
+        evaluate(&self.root, values)
+    }
+}
+
+fn evaluate(node: &Node, values: &[f32]) -> f32 {
+    match node {
+        Node::Number(value) => *value,
+        Node::Variable(index) => values[*index],
+        Node::Negate(operand) => -evaluate(operand, values),
+        Node::Binary(operator, left, right) => {
+            let (left, right) = (evaluate(left, values), evaluate(right, values));
+            match operator {
+                Operator::Add => left + right,
+                Operator::Subtract => left - right,
+                Operator::Multiply => left * right,
+                Operator::Divide => left / right,
+                Operator::Power => signed_pow(left, right),
+            }
+        },
+        Node::Call(function, arguments) => {
+            let arguments: Vec<f32> = arguments.iter().map(|argument| evaluate(argument, values)).collect();
+            function.apply(&arguments)
+        },
+    }
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    variables: &'a [&'a str],
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.chars.get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            },
+            Some(c) => Err(format!("expected '{expected}' but found '{c}' at {}", self.position + 1)),
+            None => Err(format!("expected '{expected}' but the expression ended")),
+        }
+    }
+
+    // Sums and differences, the lowest precedence
+    fn expression(&mut self) -> Result<Node, String> {
+        let mut node = self.term()?;
+        while let Some(operator) = match self.peek() {
+            Some('+') => Some(Operator::Add),
+            Some('-') => Some(Operator::Subtract),
+            _ => None,
+        } {
+            self.position += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.term()?));
+        }
+        Ok(node)
+    }
+
+    fn term(&mut self) -> Result<Node, String> {
+        let mut node = self.unary()?;
+        while let Some(operator) = match self.peek() {
+            Some('*') => Some(Operator::Multiply),
+            Some('/') => Some(Operator::Divide),
+            _ => None,
+        } {
+            self.position += 1;
+            node = Node::Binary(operator, Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(Node::Negate(Box::new(self.unary()?)))
+            },
+            Some('+') => {
+                self.position += 1;
+                self.unary()
+            },
+            _ => self.power(),
+        }
+    }
+
+    // Right associative, binding tighter than a leading minus
+    fn power(&mut self) -> Result<Node, String> {
+        let base = self.primary()?;
+        if self.peek() == Some('^') {
+            self.position += 1;
+            let exponent = self.unary()?;
+            return Ok(Node::Binary(Operator::Power, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.peek() {
+            Some('(') => {
+                self.position += 1;
+                let node = self.expression()?;
+                self.expect(')')?;
+                Ok(node)
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' => self.number(),
+            Some(c) if c.is_alphabetic() || c == '_' => self.identifier(),
+            Some(c) => Err(format!("unexpected '{c}' at {}", self.position + 1)),
+            None => Err("the expression ended early".to_string()),
+        }
+    }
+
+    fn number(&mut self) -> Result<Node, String> {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|c| c.is_ascii_digit() || *c == '.') {
+            self.position += 1;
+        }
+        // Exponent notation such as 1e-3
+        if self.chars.get(self.position).is_some_and(|c| *c == 'e' || *c == 'E') {
+            let mark = self.position;
+            self.position += 1;
+            if self.chars.get(self.position).is_some_and(|c| *c == '-' || *c == '+') {
+                self.position += 1;
+            }
+            if self.chars.get(self.position).is_some_and(|c| c.is_ascii_digit()) {
+                while self.chars.get(self.position).is_some_and(|c| c.is_ascii_digit()) {
+                    self.position += 1;
+                }
+            } else {
+                self.position = mark;
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse::<f32>()
+            .map(Node::Number)
+            .map_err(|_| format!("'{text}' is not a number"))
+    }
+
+    fn identifier(&mut self) -> Result<Node, String> {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|c| c.is_alphanumeric() || *c == '_') {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+
+        if let Some(index) = self.variables.iter().position(|variable| *variable == name) {
+            return Ok(Node::Variable(index));
+        }
+        match name.as_str() {
+            "pi" => return Ok(Node::Number(std::f32::consts::PI)),
+            "tau" => return Ok(Node::Number(std::f32::consts::TAU)),
+            _ => {},
+        }
+
+        let Some(function) = Function::parse(&name) else {
+            return Err(format!("unknown name '{name}', expected one of {}", self.variables.join(", ")));
+        };
+        self.expect('(')?;
+        let mut arguments = vec![self.expression()?];
+        while self.peek() == Some(',') {
+            self.position += 1;
+            arguments.push(self.expression()?);
+        }
+        self.expect(')')?;
+        if arguments.len() != function.arity() {
+            return Err(format!("{name} takes {} argument(s), got {}", function.arity(), arguments.len()));
+        }
+        Ok(Node::Call(function, arguments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str, values: &[f32]) -> f32 {
+        Expression::parse(source, &["x", "y", "z"]).unwrap().evaluate(values)
+    }
+
+    #[test]
+    fn multiplication_binds_tighter_than_addition() {
+        assert_eq!(evaluate("1+2*3", &[]), 7.);
+        assert_eq!(evaluate("(1+2)*3", &[]), 9.);
+        assert_eq!(evaluate("8-2-1", &[]), 5.);
+        assert_eq!(evaluate("8/2/2", &[]), 2.);
+    }
+
+    #[test]
+    fn power_is_right_associative() {
+        assert_eq!(evaluate("2^3^2", &[]), 512.);
+        assert_eq!(evaluate("2^-1", &[]), 0.5);
+    }
+
+    #[test]
+    fn leading_minus_applies_after_power() {
+        assert_eq!(evaluate("-2^2", &[]), -4.);
+        assert_eq!(evaluate("(-2)^2", &[]), 4.);
+    }
+
+    #[test]
+    fn variables_and_functions() {
+        assert_eq!(evaluate("x + 2*y - z", &[1., 2., 3.]), 2.);
+        assert_eq!(evaluate("clamp(x, 0, 1)", &[1.5, 0., 0.]), 1.);
+        assert_eq!(evaluate("mix(0, 10, 0.25)", &[]), 2.5);
+        assert_eq!(evaluate("1e-3 * 1000", &[]), 1.);
+    }
+
+    #[test]
+    fn wrong_argument_count_is_an_error() {
+        let error = Expression::parse("pow(x)", &["x"]).unwrap_err();
+        assert!(error.contains("pow takes 2"), "{error}");
+        assert!(Expression::parse("sin(x, x)", &["x"]).is_err());
+    }
+
+    #[test]
+    fn unknown_identifier_is_an_error() {
+        let error = Expression::parse("x + w", &["x"]).unwrap_err();
+        assert!(error.contains("unknown name 'w'"), "{error}");
+    }
+
+    #[test]
+    fn trailing_tokens_are_an_error() {
+        assert!(Expression::parse("1 2", &[]).is_err());
+        assert!(Expression::parse("(1))", &[]).is_err());
+        assert!(Expression::parse("1 +", &[]).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use prismatic_color::{Color as P_Color, ColorModel};
+
+use crate::annotations::WorldLabels;
+use crate::color_models::model_entry;
+use crate::colorimetry::{linear_to_srgb, oklab_to_srgb, srgb_to_oklab};
+use crate::comparison::{ComparisonGizmos, ComparisonSettings};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{place_color, VisualizationTarget, SCALE};
+
+const SWATCH_SIZE: f32 = 18.;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientSpace {
+    Rgb,
+    Hsv,
+    Hcl,
+    Yuv,
+    OkLab,
+}
+
+impl GradientSpace {
+    pub const ALL: [GradientSpace; 5] = [GradientSpace::Rgb, GradientSpace::Hsv, GradientSpace::Hcl, GradientSpace::Yuv, GradientSpace::OkLab];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GradientSpace::Rgb => "RGB",
+            GradientSpace::Hsv => "HSV",
+            GradientSpace::Hcl => "HCL",
+            GradientSpace::Yuv => "YUV",
+            GradientSpace::OkLab => "OKLab",
+        }
+    }
+
+    fn model(&self) -> Option<ColorModel> {
+        match self {
+            GradientSpace::Rgb => Some(ColorModel::RGBA),
+            GradientSpace::Hsv => Some(ColorModel::CubicHSVA),
+            GradientSpace::Hcl => Some(ColorModel::SphericalHCLA),
+            GradientSpace::Yuv => Some(ColorModel::YUVA),
+            GradientSpace::OkLab => None,
+        }
+    }
+
+    // The sRGB color `t` of the way from `start` to `end`, interpolated in this space's channels
+    pub fn interpolate(&self, start: [f32; 3], end: [f32; 3], t: f32) -> [f32; 3] {
+        let Some(model) = self.model() else {
+            return oklab_to_srgb(srgb_to_oklab(start).lerp(srgb_to_oklab(end), t));
+        };
+
+        let channels = |srgb: [f32; 3]| {
+            let (a, b, c, _) = P_Color::from_tuple((srgb[0], srgb[1], srgb[2], 1.), ColorModel::RGBA).convert_color(model).to_tuple();
+            [a, b, c]
+        };
+        let (from, to) = (channels(start), channels(end));
+        let infos = model_entry(model).channels;
+
+        let value = [0, 1, 2].map(|axis| {
+            let mut delta = to[axis] - from[axis];
+            // Hue takes the short way around
+            if infos[axis].cyclic {
+                let span = infos[axis].span();
+                delta -= span * (delta / span).round();
+            }
+            from[axis] + delta * t
+        });
+
+        let [r, g, b, _] = P_Color::from_tuple((value[0], value[1], value[2], 1.), model).to_rgb().to_array();
+        [r, g, b]
+    }
+}
+
+// Two endpoint colors whose interpolation paths are compared across models
+#[derive(Debug, Clone, PartialEq)]
+pub struct GradientPaths {
+    pub enabled: bool,
+    // Linear RGB, as edited by the color pickers
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    pub stops: usize,
+    pub spaces: Vec<GradientSpace>,
+}
+
+impl Default for GradientPaths {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: [0.8, 0.02, 0.02],
+            end: [0.02, 0.1, 0.8],
+            stops: 9,
+            spaces: GradientSpace::ALL.to_vec(),
+        }
+    }
+}
+
+impl GradientPaths {
+    // The sRGB stops of the gradient in `space`, endpoints included
+    pub fn stops(&self, space: GradientSpace) -> Vec<[f32; 3]> {
+        let (start, end) = (self.start.map(linear_to_srgb), self.end.map(linear_to_srgb));
+        let divisions = self.stops.max(2) - 1;
+        (0..=divisions)
+            .map(|stop| space.interpolate(start, end, stop as f32 / divisions as f32))
+            .collect()
+    }
+}
+
+fn swatch_color(srgb: [f32; 3]) -> Color {
+    Color::srgb(srgb[0], srgb[1], srgb[2])
+}
+
+// Draws the interpolation paths through each visualization on screen
+pub fn draw_gradient_paths(
+    mut gizmos: Gizmos,
+    mut comparison_gizmos: Gizmos<ComparisonGizmos>,
+    mut labels: ResMut<WorldLabels>,
+    settings: Res<VisualizationSettings>,
+    comparison: Res<ComparisonSettings>,
+) {
+    gradient_paths(&mut gizmos, &mut labels, &settings, &VisualizationTarget::primary());
+    if let Some((settings, target)) = comparison.shown() {
+        if target.on_comparison_camera() {
+            gradient_paths(&mut comparison_gizmos, &mut labels, settings, &target);
+        } else {
+            gradient_paths(&mut gizmos, &mut labels, settings, &target);
+        }
+    }
+}
+
+// Each interpolation path as a polyline through the visualization space
+fn gradient_paths<Config: GizmoConfigGroup>(
+    gizmos: &mut Gizmos<'_, '_, Config>,
+    labels: &mut WorldLabels,
+    settings: &VisualizationSettings,
+    target: &VisualizationTarget,
+) {
+    let gradient = &settings.gradient;
+    if !gradient.enabled {return};
+
+    let place = |srgb: [f32; 3]| {
+        place_color(P_Color::from_tuple((srgb[0], srgb[1], srgb[2], 1.), ColorModel::RGBA), settings) * SCALE * settings.viz_scale + target.offset
+    };
+
+    for space in &gradient.spaces {
+        let stops = gradient.stops(*space);
+        let points: Vec<(Vec3, Color)> = stops.iter().map(|srgb| (place(*srgb), swatch_color(*srgb))).collect();
+        gizmos.linestrip_gradient(points.iter().copied());
+        for (point, color) in &points {
+            gizmos.sphere(Isometry3d::from_translation(*point), SCALE * 0.01, *color);
+        }
+        if let Some((midpoint, _)) = points.get(points.len() / 2) {
+            labels.add(target, *midpoint, space.name());
+        }
+    }
+}
+
+// A strip of stop swatches per space, so the gradients can be compared flat
+pub fn gradient_swatches(mut contexts: EguiContexts, settings: Res<VisualizationSettings>) {
+    let gradient = &settings.gradient;
+    if !gradient.enabled {return};
+    let Ok(ctx) = contexts.ctx_mut() else {return};
+
+    egui::Window::new("Gradient Paths")
+        .resizable(false)
+        .show(ctx, |ui| {
+            egui::Grid::new("gradient_swatches").show(ui, |ui| {
+                for space in &gradient.spaces {
+                    ui.label(space.name());
+                    ui.horizontal(|ui| {
+                        ui.spacing_mut().item_spacing.x = 0.;
+                        for srgb in gradient.stops(*space) {
+                            let [r, g, b] = srgb.map(|channel| (channel.clamp(0., 1.) * 255.).round() as u8);
+                            let (rect, _) = ui.allocate_exact_size(egui::vec2(SWATCH_SIZE, SWATCH_SIZE), egui::Sense::hover());
+                            ui.painter().rect_filled(rect, 0., egui::Color32::from_rgb(r, g, b));
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        });
+}
@@ -0,0 +1,114 @@
+use bevy::prelude::*;
+
+use crate::color_models::ChannelInfo;
+use crate::ui::ColorChannel;
+use crate::colorimetry::{linear_to_srgb, srgb_to_linear};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ToneMapping {
+    // Out of range components are cut off at the display limits
+    Clip,
+    // Luminance based, reaching white at the peak value
+    Reinhard,
+    // Narkowicz fit of the ACES filmic curve, per component
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::Clip, ToneMapping::Reinhard, ToneMapping::AcesFilmic];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::Clip => "Clip",
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::AcesFilmic => "ACES Filmic",
+        }
+    }
+}
+
+// Sampling past the unit range of each channel, displayed through a tone curve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtendedRange {
+    pub enabled: bool,
+    // Largest channel value and perceptual offset, in multiples of the unit range
+    pub peak: f32,
+    // Treat the model's RGB output as linear light instead of sRGB encoded values
+    pub linear_light: bool,
+    pub tone_mapping: ToneMapping,
+}
+
+impl Default for ExtendedRange {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            peak: 4.,
+            linear_light: false,
+            tone_mapping: ToneMapping::Reinhard,
+        }
+    }
+}
+
+impl ExtendedRange {
+    // Linear channels stretch up to the peak and down past zero, hue keeps its single turn
+    pub fn channel_info(&self, info: &ChannelInfo) -> ChannelInfo {
+        if !self.enabled || info.cyclic {
+            return *info;
+        }
+        ChannelInfo {
+            range: ((info.range.0 * self.peak).min(-info.span()), info.range.1 * self.peak),
+            ..*info
+        }
+    }
+
+    // Pulls a channel selection back inside the range allowed now, after the range was narrowed
+    pub fn clamp_channel(&self, channel: &mut ColorChannel, info: &ChannelInfo) {
+        let (min, max) = self.channel_info(info).range;
+        channel.start = channel.start.clamp(min, max);
+        channel.end = channel.end.clamp(min, max);
+    }
+
+    pub fn component_limit_max(&self) -> f32 {
+        if self.enabled {self.peak} else {1.}
+    }
+
+    // Whether the model's RGB output is read as linear light
+    pub fn reads_linear_light(&self) -> bool {
+        self.enabled && self.linear_light
+    }
+
+    // The model's RGB output as sRGB encoded values, so sampling, placement and display all see the same color
+    pub fn encode_output(&self, rgb: [f32; 3]) -> [f32; 3] {
+        if self.reads_linear_light() {rgb.map(linear_to_srgb)} else {rgb}
+    }
+
+    // Tone mapped display color of an sRGB encoded RGBA sample
+    pub fn display_color(&self, rgba: [f32; 4]) -> Color {
+        let [r, g, b, a] = rgba;
+        if !self.enabled {
+            return Color::srgba(r, g, b, a);
+        }
+
+        let linear = Vec3::from([r, g, b].map(srgb_to_linear));
+        let mapped = self.tone_map(linear.max(Vec3::ZERO)).clamp(Vec3::ZERO, Vec3::ONE);
+        Color::linear_rgba(mapped.x, mapped.y, mapped.z, a)
+    }
+
+    fn tone_map(&self, linear: Vec3) -> Vec3 {
+        match self.tone_mapping {
+            ToneMapping::Clip => linear,
+            ToneMapping::Reinhard => {
+                let luminance = linear.dot(Vec3::new(0.2126, 0.7152, 0.0722));
+                if luminance <= 0. {
+                    return Vec3::ZERO;
+                }
+                let white = self.peak.max(1.);
+                let mapped = luminance * (1. + luminance / (white * white)) / (1. + luminance);
+                linear * (mapped / luminance)
+            },
+            ToneMapping::AcesFilmic => {
+                let curve = |x: f32| (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14);
+                Vec3::from(linear.to_array().map(curve))
+            },
+        }
+    }
+}
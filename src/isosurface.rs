@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_asset::RenderAssetUsages;
+use prismatic_color::Color as P_Color;
+
+use crate::colorimetry::{linear_to_srgb, opponent_to_polar, srgb_to_lab, DeltaEMetric};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{alpha_values, get_point_and_color, mesh_axes, SCALE};
+
+// The six tetrahedra around the 0-7 diagonal of a cell, corners numbered by their x + 2y + 4z offset.
+// Splitting cells this way leaves no ambiguous cases, so no lookup table is needed.
+const CELL_TETRAHEDRA: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 3, 2, 7],
+    [0, 2, 6, 7],
+    [0, 6, 4, 7],
+    [0, 4, 5, 7],
+    [0, 5, 1, 7],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IsoField {
+    // CIELAB L*
+    Lightness,
+    // CIELAB C*ab
+    Chroma,
+    // Distance to the reference color in the selected ΔE metric
+    DeltaE,
+}
+
+impl IsoField {
+    pub const ALL: [IsoField; 3] = [IsoField::Lightness, IsoField::Chroma, IsoField::DeltaE];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            IsoField::Lightness => "Lightness",
+            IsoField::Chroma => "Chroma",
+            IsoField::DeltaE => "ΔE From Reference",
+        }
+    }
+
+    // Values the level slider covers
+    pub fn range(&self, metric: DeltaEMetric) -> (f32, f32) {
+        match (self, metric) {
+            (IsoField::Lightness, _) => (0., 100.),
+            (IsoField::Chroma, _) => (0., 150.),
+            (IsoField::DeltaE, DeltaEMetric::OkLab) => (0., 1.),
+            (IsoField::DeltaE, _) => (0., 100.),
+        }
+    }
+}
+
+// A surface of constant lightness, chroma or ΔE drawn in place of the volume shell
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Isosurface {
+    pub enabled: bool,
+    pub field: IsoField,
+    pub level: f32,
+    // Linear RGB, as edited by the color picker
+    pub reference: [f32; 3],
+}
+
+impl Default for Isosurface {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            field: IsoField::Lightness,
+            level: 50.,
+            reference: [0.18, 0.18, 0.18],
+        }
+    }
+}
+
+impl Isosurface {
+    pub fn value(&self, srgb: [f32; 3], metric: DeltaEMetric) -> f32 {
+        match self.field {
+            IsoField::Lightness => srgb_to_lab(srgb).x,
+            IsoField::Chroma => {
+                let lab = srgb_to_lab(srgb);
+                opponent_to_polar(lab.y, lab.z).1
+            },
+            IsoField::DeltaE => metric.delta_e(srgb, self.reference.map(linear_to_srgb)),
+        }
+    }
+
+    // Moves the level to the middle of the new field's range
+    pub fn set_field(&mut self, field: IsoField, metric: DeltaEMetric) {
+        if self.field == field {return};
+        self.field = field;
+        let (min, max) = field.range(metric);
+        self.level = (min + max) / 2.;
+    }
+}
+
+// A lattice corner of the cell being polygonized
+#[derive(Clone, Copy)]
+struct Corner {
+    index: usize,
+    // Unwrapped past the end of a closed hue axis, so edges never cross the seam the long way
+    channels: [f32; 3],
+    value: f32,
+    point: Vec3,
+}
+
+// Marches every lattice cell of each alpha layer, placing surface vertices by evaluating
+// the interpolated channel values through `get_point_and_color` so they sit on the curved solid
+pub fn isosurface_mesh(settings: &VisualizationSettings, display_color: impl Fn(P_Color) -> Color) -> Mesh {
+    let iso = &settings.isosurface;
+    let axes = mesh_axes(settings);
+    let infos = settings.model_channels();
+    let alpha_values = alpha_values(settings);
+    let lengths = [axes[0].len(), axes[1].len(), axes[2].len()];
+    let flat = |index: [usize; 3]| (index[0] * lengths[1] + index[1]) * lengths[2] + index[2];
+    let scale = SCALE * settings.viz_scale;
+
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut colors: Vec<[f32; 4]> = Vec::new();
+    let mut indices: Vec<u32> = Vec::new();
+
+    for (layer, &alpha) in alpha_values.iter().enumerate() {
+        let layer_offset = settings.alpha_layout.offset(layer, alpha_values.len());
+        let evaluate = |channels: [f32; 3]| {
+            let (point, color) = get_point_and_color((channels[0], channels[1], channels[2], alpha), settings);
+            (Vec3::from(point) + layer_offset, color)
+        };
+
+        // Field value and position of every lattice sample
+        let mut samples = Vec::with_capacity(lengths.iter().product());
+        for index_of_a in 0..lengths[0] {
+            for index_of_b in 0..lengths[1] {
+                for index_of_c in 0..lengths[2] {
+                    let (point, color) = evaluate([axes[0].value(index_of_a), axes[1].value(index_of_b), axes[2].value(index_of_c)]);
+                    let [r, g, b, _] = color.to_rgb().to_array();
+                    samples.push((iso.value([r, g, b], settings.delta_e_metric), point));
+                }
+            }
+        }
+
+        // Surface vertices shared between cells, keyed by the lattice edge they lie on
+        let mut edge_vertices: HashMap<(usize, usize), u32> = HashMap::new();
+        let mut edge_vertex = |inside: &Corner, outside: &Corner, positions: &mut Vec<Vec3>, colors: &mut Vec<[f32; 4]>| -> u32 {
+            let key = (inside.index.min(outside.index), inside.index.max(outside.index));
+            *edge_vertices.entry(key).or_insert_with(|| {
+                let t = (iso.level - inside.value) / (outside.value - inside.value);
+                let channels = [0, 1, 2].map(|axis| {
+                    let value = inside.channels[axis] + (outside.channels[axis] - inside.channels[axis]) * t;
+                    let info = infos[axis];
+                    if info.cyclic {info.range.0 + (value - info.range.0).rem_euclid(info.span())} else {value}
+                });
+                let (point, color) = evaluate(channels);
+                positions.push(point * scale);
+                colors.push(display_color(color).to_linear().to_f32_array());
+                (positions.len() - 1) as u32
+            })
+        };
+
+        for index_of_a in 0..lengths[0] {
+            for index_of_b in 0..lengths[1] {
+                for index_of_c in 0..lengths[2] {
+                    let index = [index_of_a, index_of_b, index_of_c];
+                    let corner = |bit: usize| -> Option<Corner> {
+                        let offset = [bit & 1, (bit >> 1) & 1, (bit >> 2) & 1];
+                        let mut corner_index = index;
+                        let mut channels = [0.; 3];
+                        for axis in 0..3 {
+                            corner_index[axis] = axes[axis].neighbor(index[axis], offset[axis])?;
+                            channels[axis] = axes[axis].value(corner_index[axis]);
+                            if corner_index[axis] < index[axis] {
+                                channels[axis] += infos[axis].span();
+                            }
+                        }
+                        let (value, point) = samples[flat(corner_index)];
+                        Some(Corner { index: flat(corner_index), channels, value, point })
+                    };
+                    let Some(corners) = (0..8).map(corner).collect::<Option<Vec<Corner>>>() else {continue};
+
+                    for tetrahedron in CELL_TETRAHEDRA {
+                        let (inside, outside): (Vec<Corner>, Vec<Corner>) =
+                            tetrahedron.iter().map(|bit| corners[*bit]).partition(|corner| corner.value >= iso.level);
+
+                        let triangles: Vec<[u32; 3]> = match (inside.len(), outside.len()) {
+                            (1, 3) => vec![[0, 1, 2].map(|i| edge_vertex(&inside[0], &outside[i], &mut positions, &mut colors))],
+                            (3, 1) => vec![[0, 1, 2].map(|i| edge_vertex(&inside[i], &outside[0], &mut positions, &mut colors))],
+                            (2, 2) => {
+                                let quad = [
+                                    edge_vertex(&inside[0], &outside[0], &mut positions, &mut colors),
+                                    edge_vertex(&inside[0], &outside[1], &mut positions, &mut colors),
+                                    edge_vertex(&inside[1], &outside[1], &mut positions, &mut colors),
+                                    edge_vertex(&inside[1], &outside[0], &mut positions, &mut colors),
+                                ];
+                                vec![[quad[0], quad[1], quad[2]], [quad[0], quad[2], quad[3]]]
+                            },
+                            _ => continue,
+                        };
+
+                        // Wind each triangle to face away from the corners above the level
+                        let inside_center = inside.iter().map(|corner| corner.point * scale).sum::<Vec3>() / inside.len() as f32;
+                        for [i1, i2, i3] in triangles {
+                            let [p1, p2, p3] = [i1, i2, i3].map(|i| positions[i as usize]);
+                            let normal = (p2 - p1).cross(p3 - p1);
+                            if normal.dot((p1 + p2 + p3) / 3. - inside_center) < 0. {
+                                indices.extend_from_slice(&[i1, i3, i2]);
+                            } else {
+                                indices.extend_from_slice(&[i1, i2, i3]);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Area weighted vertex normals for smooth shading
+    let mut normals = vec![Vec3::ZERO; positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [p1, p2, p3] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+        let normal = (p2 - p1).cross(p3 - p1);
+        for i in triangle {
+            normals[*i as usize] += normal;
+        }
+    }
+
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleList, RenderAssetUsages::default());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions.into_iter().map(<[f32; 3]>::from).collect::<Vec<_>>());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals.into_iter().map(|normal| normal.normalize_or_zero().to_array()).collect::<Vec<_>>());
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh.insert_indices(Indices::U32(indices));
+    mesh
+}
@@ -0,0 +1,358 @@
+use std::fmt::Write as _;
+use std::path::Path;
+use std::sync::Arc;
+
+use prismatic_color::{Color as P_Color, ColorModel};
+
+use crate::colorimetry::srgb_to_linear;
+use crate::ui::VisualizationSettings;
+use crate::visualization::sample_colors;
+
+// Lattice sizes offered for export, the common sizes grading tools accept
+pub const EXPORT_SIZES: [usize; 3] = [17, 33, 65];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LutDimension {
+    OneD,
+    ThreeD,
+}
+
+// A 1D or 3D lookup table in the Adobe/Resolve .cube format
+#[derive(Debug, Clone, PartialEq)]
+pub struct CubeLut {
+    pub title: String,
+    pub dimension: LutDimension,
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    // Red varies fastest, then green, then blue
+    pub table: Vec<[f32; 3]>,
+}
+
+impl CubeLut {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Self::parse(&text)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut title = String::new();
+        let mut size = None;
+        let mut domain_min = [0.; 3];
+        let mut domain_max = [1.; 3];
+        let mut table = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("line {}: {message}", number + 1);
+            let mut words = line.split_whitespace();
+            let keyword = words.next().unwrap_or_default();
+            let floats = |words: std::str::SplitWhitespace| -> Result<Vec<f32>, String> {
+                words.map(|word| word.parse::<f32>().map_err(|_| error(&format!("'{word}' is not a number")))).collect()
+            };
+
+            match keyword {
+                "TITLE" => title = line["TITLE".len()..].trim().trim_matches('"').to_string(),
+                "LUT_1D_SIZE" | "LUT_3D_SIZE" => {
+                    let dimension = if keyword == "LUT_1D_SIZE" {LutDimension::OneD} else {LutDimension::ThreeD};
+                    let value = words.next().and_then(|word| word.parse::<usize>().ok()).ok_or_else(|| error("missing table size"))?;
+                    if value < 2 {
+                        return Err(error("table size must be at least 2"));
+                    }
+                    size = Some((dimension, value));
+                },
+                "DOMAIN_MIN" | "DOMAIN_MAX" => {
+                    let values = floats(words)?;
+                    let [r, g, b] = values[..] else {return Err(error("expected three values"))};
+                    if keyword == "DOMAIN_MIN" {domain_min = [r, g, b]} else {domain_max = [r, g, b]};
+                },
+                // Resolve writes the shared input range on a single line
+                "LUT_1D_INPUT_RANGE" | "LUT_3D_INPUT_RANGE" => {
+                    let values = floats(words)?;
+                    let [min, max] = values[..] else {return Err(error("expected two values"))};
+                    domain_min = [min; 3];
+                    domain_max = [max; 3];
+                },
+                // Video range flags and vendor headers don't change the table
+                _ if keyword.starts_with(|c: char| c.is_ascii_alphabetic()) => {},
+                _ => {
+                    let values = floats(line.split_whitespace())?;
+                    let [r, g, b] = values[..] else {return Err(error("expected three values"))};
+                    table.push([r, g, b]);
+                },
+            }
+        }
+
+        let Some((dimension, size)) = size else {
+            return Err("missing LUT_1D_SIZE or LUT_3D_SIZE".to_string());
+        };
+        let expected = match dimension {
+            LutDimension::OneD => size,
+            LutDimension::ThreeD => size * size * size,
+        };
+        if table.len() != expected {
+            return Err(format!("expected {expected} table entries, found {}", table.len()));
+        }
+
+        Ok(Self { title, dimension, size, domain_min, domain_max, table })
+    }
+
+    // A 3D table sampling `transform` over the unit cube
+    pub fn bake(title: &str, size: usize, transform: impl Fn([f32; 3]) -> [f32; 3]) -> Self {
+        let last = (size - 1) as f32;
+        let mut table = Vec::with_capacity(size * size * size);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    table.push(transform([r, g, b].map(|index| index as f32 / last)));
+                }
+            }
+        }
+        Self {
+            title: title.to_string(),
+            dimension: LutDimension::ThreeD,
+            size,
+            domain_min: [0.; 3],
+            domain_max: [1.; 3],
+            table,
+        }
+    }
+
+    pub fn to_cube(&self) -> String {
+        let mut cube = String::new();
+        let _ = writeln!(cube, "TITLE \"{}\"", self.title);
+        let size_keyword = match self.dimension {
+            LutDimension::OneD => "LUT_1D_SIZE",
+            LutDimension::ThreeD => "LUT_3D_SIZE",
+        };
+        let _ = writeln!(cube, "{size_keyword} {}", self.size);
+        let _ = writeln!(cube, "DOMAIN_MIN {} {} {}", self.domain_min[0], self.domain_min[1], self.domain_min[2]);
+        let _ = writeln!(cube, "DOMAIN_MAX {} {} {}", self.domain_max[0], self.domain_max[1], self.domain_max[2]);
+        for entry in &self.table {
+            let [r, g, b] = entry.map(|value| if value.is_finite() {value} else {0.});
+            let _ = writeln!(cube, "{r:.6} {g:.6} {b:.6}");
+        }
+        cube
+    }
+
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_cube())
+    }
+
+    // Input position along each axis in table cells
+    fn cell_coordinates(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let last = (self.size - 1) as f32;
+        [0, 1, 2].map(|channel| {
+            let span = self.domain_max[channel] - self.domain_min[channel];
+            let t = if span > 0. {(rgb[channel] - self.domain_min[channel]) / span} else {0.};
+            t.clamp(0., 1.) * last
+        })
+    }
+
+    pub fn apply(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let coordinates = self.cell_coordinates(rgb);
+        let split = |coordinate: f32| {
+            let lower = (coordinate.floor() as usize).min(self.size - 2);
+            (lower, coordinate - lower as f32)
+        };
+
+        match self.dimension {
+            LutDimension::OneD => [0, 1, 2].map(|channel| {
+                let (lower, fraction) = split(coordinates[channel]);
+                let (low, high) = (self.table[lower][channel], self.table[lower + 1][channel]);
+                low + (high - low) * fraction
+            }),
+            LutDimension::ThreeD => {
+                let [(r, fr), (g, fg), (b, fb)] = coordinates.map(split);
+                let entry = |dr: usize, dg: usize, db: usize| self.table[(r + dr) + (g + dg) * self.size + (b + db) * self.size * self.size];
+                let lerp = |low: [f32; 3], high: [f32; 3], t: f32| [0, 1, 2].map(|channel| low[channel] + (high[channel] - low[channel]) * t);
+
+                // Trilinear, collapsing red, then green, then blue
+                let g0 = lerp(lerp(entry(0, 0, 0), entry(1, 0, 0), fr), lerp(entry(0, 1, 0), entry(1, 1, 0), fr), fg);
+                let g1 = lerp(lerp(entry(0, 0, 1), entry(1, 0, 1), fr), lerp(entry(0, 1, 1), entry(1, 1, 1), fr), fg);
+                lerp(g0, g1, fb)
+            },
+        }
+    }
+}
+
+// The perceptual offset and gamma of `get_point_and_color`, with the current settings, as an RGB to RGB table
+pub fn bake_remapping(settings: &VisualizationSettings, size: usize) -> CubeLut {
+    CubeLut::bake("Prismatic perceptual offset", size, |rgb| {
+        let [r, g, b] = rgb;
+        let channels = match settings.active_custom_model() {
+            Some(model) => model.from_linear_rgb(rgb.map(srgb_to_linear)),
+            None => {
+                let (x, y, z, _) = P_Color::from_tuple((r, g, b, 1.), ColorModel::RGBA).convert_color(settings.color_model).to_tuple();
+                [x, y, z]
+            },
+        };
+        let [r, g, b, _] = sample_colors((channels[0], channels[1], channels[2], 1.), settings).transformed.to_rgb().to_array();
+        [r, g, b]
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LutView {
+    // Every sample moved and recolored by the LUT
+    Transformed,
+    // Arrows from each sample's original position to where the LUT sends it
+    Displacement,
+}
+
+// A loaded .cube file applied to every lattice sample before positioning
+#[derive(Clone, PartialEq)]
+pub struct LutSettings {
+    pub path: String,
+    pub lut: Option<Arc<CubeLut>>,
+    pub enabled: bool,
+    pub view: LutView,
+    // Outcome of the last load, shown under the path
+    pub status: Option<String>,
+    pub export_path: String,
+    pub export_size: usize,
+    pub export_status: Option<String>,
+}
+
+impl Default for LutSettings {
+    fn default() -> Self {
+        Self {
+            path: "grade.cube".to_string(),
+            lut: None,
+            enabled: false,
+            view: LutView::Transformed,
+            status: None,
+            export_path: "prismatic.cube".to_string(),
+            export_size: 33,
+            export_status: None,
+        }
+    }
+}
+
+impl LutSettings {
+    pub fn load(&mut self) {
+        match CubeLut::load(Path::new(&self.path)) {
+            Ok(lut) => {
+                let kind = match lut.dimension {
+                    LutDimension::OneD => "1D",
+                    LutDimension::ThreeD => "3D",
+                };
+                self.status = Some(format!("Loaded {kind} LUT of size {} {}", lut.size, lut.title));
+                self.lut = Some(Arc::new(lut));
+                self.enabled = true;
+            },
+            Err(error) => self.status = Some(format!("Load failed: {error}")),
+        }
+    }
+
+    // Writes a table baked by `bake_remapping` to the export path
+    pub fn save_export(&mut self, lut: &CubeLut) {
+        self.export_status = Some(match lut.save(Path::new(&self.export_path)) {
+            Ok(()) => format!("Wrote {0}x{0}x{0} LUT to {1}", lut.size, self.export_path),
+            Err(error) => format!("Export failed: {error}"),
+        });
+    }
+
+    pub fn active(&self) -> Option<&CubeLut> {
+        self.lut.as_deref().filter(|_| self.enabled)
+    }
+
+    pub fn shows_displacement(&self) -> bool {
+        self.active().is_some() && self.view == LutView::Displacement
+    }
+
+    // The color after the LUT, unchanged while none is active
+    pub fn apply(&self, color: P_Color) -> P_Color {
+        let Some(lut) = self.active() else {return color};
+        let [r, g, b, a] = color.to_rgb().to_array();
+        let [r, g, b] = lut.apply([r, g, b]);
+        P_Color::from_tuple((r, g, b, a), ColorModel::RGBA)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color_models::ColorModelCategory;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (actual_value, expected_value) in actual.iter().zip(expected) {
+            assert!((actual_value - expected_value).abs() < 1e-5, "{actual:?} != {expected:?}");
+        }
+    }
+
+    #[test]
+    fn identity_round_trips_through_cube_text() {
+        let lut = CubeLut::bake("identity", 2, |rgb| rgb);
+        let parsed = CubeLut::parse(&lut.to_cube()).unwrap();
+        assert_eq!(parsed, lut);
+        // Red varies fastest
+        assert_eq!(parsed.table[1], [1., 0., 0.]);
+        assert_eq!(parsed.table[2], [0., 1., 0.]);
+        assert_eq!(parsed.table[4], [0., 0., 1.]);
+        for rgb in [[0., 0., 0.], [0.25, 0.5, 0.75], [1., 1., 1.], [0.9, 0.1, 0.4]] {
+            assert_close(parsed.apply(rgb), rgb);
+        }
+    }
+
+    #[test]
+    fn one_dimensional_tables_interpolate_per_channel() {
+        let lut = CubeLut::parse("LUT_1D_SIZE 3\n0 0 0\n0.25 0.5 1\n1 1 1\n").unwrap();
+        assert_close(lut.apply([0.25, 0.5, 0.75]), [0.125, 0.5, 1.]);
+        assert_close(lut.apply([1., 0., 0.5]), [1., 0., 1.]);
+    }
+
+    #[test]
+    fn domain_scales_the_input() {
+        let text = "LUT_1D_SIZE 2\nDOMAIN_MIN 0 0 0\nDOMAIN_MAX 2 2 2\n0 0 0\n1 1 1\n";
+        let lut = CubeLut::parse(text).unwrap();
+        assert_close(lut.apply([1., 0.5, 2.]), [0.5, 0.25, 1.]);
+        // Inputs outside the domain clamp to its ends
+        assert_close(lut.apply([3., -1., 0.]), [1., 0., 0.]);
+    }
+
+    #[test]
+    fn unknown_keywords_are_skipped() {
+        let text = "TITLE \"graded\"\nLUT_IN_VIDEO_RANGE\nLUT_OUT_VIDEO_RANGE\nLUT_3D_SIZE 2\n0 0 0\n1 0 0\n0 1 0\n1 1 0\n0 0 1\n1 0 1\n0 1 1\n1 1 1\n";
+        let lut = CubeLut::parse(text).unwrap();
+        assert_eq!(lut.title, "graded");
+        assert_eq!(lut.table.len(), 8);
+    }
+
+    #[test]
+    fn neutral_remapping_bakes_to_identity() {
+        let settings = VisualizationSettings {
+            color_model_category: ColorModelCategory::Primary,
+            color_model: ColorModel::RGBA,
+            component_limit: (1., 1., 1.),
+            gamma: (2.2, 2.2, 2.2),
+            gamma_deform: false,
+            ..Default::default()
+        };
+        let size = 5;
+        let parsed = CubeLut::parse(&bake_remapping(&settings, size).to_cube()).unwrap();
+        assert_eq!(parsed.size, size);
+
+        let last = (size - 1) as f32;
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    let expected = [r, g, b].map(|index| index as f32 / last);
+                    let entry = parsed.table[r + g * size + b * size * size];
+                    for (actual, wanted) in entry.iter().zip(expected) {
+                        assert!((actual - wanted).abs() < 1e-4, "{entry:?} != {expected:?} at {r} {g} {b}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn table_size_has_to_match() {
+        let error = CubeLut::parse("LUT_3D_SIZE 2\n0 0 0\n1 1 1\n").unwrap_err();
+        assert_eq!(error, "expected 8 table entries, found 2");
+    }
+}
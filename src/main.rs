@@ -1,26 +1,71 @@
 //Digital Greenery
 //Prismatic Color Visualizer
 
-use bevy::{prelude::*, render::view::NoIndirectDrawing};
+use bevy::{prelude::*, render::view::RenderLayers};
 use bevy_egui::{EguiPlugin, EguiPrimaryContextPass};
 
+mod annotations;
+use annotations::{clear_world_labels, draw_annotations, paint_world_labels, WorldLabels};
+
 mod camera;
+mod color_models;
+mod colorimetry;
 use bevy_pointcloud::point_cloud_material::PointCloudMaterial;
-use camera::camera_controls;
+use camera::{camera_controls, viewer_camera, ComparisonCamera, PrimaryCamera};
+
+mod comparison;
+mod contrast;
+mod custom_model;
+mod cvd;
+mod expression;
+mod gradient;
+use gradient::{draw_gradient_paths, gradient_swatches};
+mod hdr;
+mod isosurface;
+mod lut;
+use comparison::{sync_comparison_camera, update_comparison_viewports, update_comparison_visualization, ComparisonGizmos, ComparisonSettings, COMPARISON_LAYER};
+
+mod report;
+use report::{apply_lattice_args, write_report, ReportExport};
+
+mod spectral;
+use spectral::draw_spectral_locus;
 
 mod ui;
 use ui::{ui_overlay, VisualizationSettings};
 
 mod visualization;
-use visualization::{spawn_3d_visualization, VisualizationMesh, SCALE};
+use visualization::{sort_translucent_quads, spawn_3d_visualization, LatticeStats, VisualizationMesh, VisualizationSlot, VisualizationTarget, SCALE};
 
-use bevy_pointcloud::{render::PointCloudRenderMode, PointCloudPlugin};
+use bevy_pointcloud::PointCloudPlugin;
 use bevy_pointcloud::point_cloud::{PointCloud};
 
 use crate::ui::{ColorChannel, StepType};
 
 
 fn main() {
+    // Headless report export: prismatic_visualizer --report <path.csv|path.json> [--model <name>] [--channels <start:end:steps>,...]
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--report") {
+        let Some(path) = args.get(index + 1).filter(|path| !path.starts_with("--")) else {
+            eprintln!("Usage: prismatic_visualizer --report <path.csv|path.json> [--model <name>] [--channels <start:end:steps>,...]");
+            std::process::exit(2);
+        };
+        let mut settings = default_settings();
+        if let Err(error) = apply_lattice_args(&args, &mut settings) {
+            eprintln!("Report export failed: {error}");
+            std::process::exit(2);
+        }
+        match write_report(&settings, std::path::Path::new(path)) {
+            Ok(report) => println!("Wrote {} samples to {}", report.samples.len(), path),
+            Err(error) => {
+                eprintln!("Report export failed: {error}");
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set( WindowPlugin {
             primary_window: Some(Window {
@@ -31,82 +76,109 @@ fn main() {
         }))
         .add_plugins(EguiPlugin::default())
         .add_plugins(PointCloudPlugin)
+        .init_gizmo_group::<ComparisonGizmos>()
+        .init_resource::<WorldLabels>()
+        .init_resource::<LatticeStats>()
+        .init_resource::<ReportExport>()
         .add_systems(Startup, setup)
-        .add_systems(Update, (update_visualization, update_gizmo_config))
+        .add_systems(First, clear_world_labels)
+        .add_systems(Update, (update_visualization, update_comparison_visualization, update_comparison_viewports, update_gizmo_config, draw_annotations, draw_spectral_locus, draw_gradient_paths))
+        .add_systems(PostUpdate, (sync_comparison_camera.before(TransformSystem::TransformPropagate), sort_translucent_quads.after(TransformSystem::TransformPropagate)))
         .add_systems(FixedUpdate, camera_controls)
-        .add_systems(EguiPrimaryContextPass, ui_overlay)
+        .add_systems(EguiPrimaryContextPass, (ui_overlay, gradient_swatches, paint_world_labels))
         .run();
 }
  
 fn setup(
-    gizmos: Gizmos,
     mut commands: Commands,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
     point_clouds: ResMut<Assets<PointCloud>>,
     point_cloud_materials: ResMut<Assets<PointCloudMaterial>>,
+    gizmo_assets: ResMut<Assets<GizmoAsset>>,
 ) {
 
-    //Needs moved into camera.rs
+    commands.spawn((viewer_camera(), PrimaryCamera));
     commands.spawn((
-        Camera3d {..Default::default()},
-        Transform::from_xyz(SCALE*2., SCALE*2., SCALE*2.).looking_at(Vec3::new(0., 0., 0.), Vec3::Z),
-        NoIndirectDrawing,
-        Msaa::Off,
-        PointCloudRenderMode {
-            use_edl: false,
-            edl_radius: 2.8,
-            edl_strength: 0.4,
-            edl_neighbour_count: 4,
-            ..Default::default()
+        viewer_camera(),
+        Camera {
+            order: 1,
+            is_active: false,
+            ..default()
         },
+        RenderLayers::layer(COMPARISON_LAYER),
+        ComparisonCamera,
     ));
 
-    let channel_settings: (ColorChannel, ColorChannel, ColorChannel) = (
-        ColorChannel { start: 0., end: 1., steps: 12, step_type: StepType::Forward },
-        ColorChannel { start: 0., end: 1., steps: 8, step_type: StepType::Inclusive },
-        ColorChannel { start: 0., end: 1., steps: 8, step_type: StepType::Inclusive},
-    );
+    // Only affects Face and Volume meshes with lit shading enabled
+    commands.insert_resource(AmbientLight {
+        brightness: 400.,
+        ..default()
+    });
+    commands.spawn((
+        DirectionalLight {
+            illuminance: 4000.,
+            ..default()
+        },
+        Transform::from_xyz(SCALE, SCALE * 0.5, SCALE * 2.).looking_at(Vec3::ZERO, Vec3::Z),
+        RenderLayers::from_layers(&[0, COMPARISON_LAYER]),
+    ));
 
-    let settings = VisualizationSettings {
-        channel_settings,
-        ..Default::default()
-    };
+    let settings = default_settings();
 
     let settings_copy = settings.clone();
 
+    commands.insert_resource(ComparisonSettings::new(settings.clone()));
     commands.insert_resource(settings);
 
-    spawn_3d_visualization(gizmos, commands, meshes, materials, point_clouds, point_cloud_materials, &settings_copy);
+    spawn_3d_visualization(commands, meshes, materials, point_clouds, point_cloud_materials, gizmo_assets, &settings_copy, &VisualizationTarget::primary());
 
 }
+
+// Starting lattice, shared by the viewer and the headless report
+fn default_settings() -> VisualizationSettings {
+    let channel_settings: (ColorChannel, ColorChannel, ColorChannel) = (
+        ColorChannel { start: 0., end: 1., steps: 12, step_type: StepType::Forward, ..Default::default() },
+        ColorChannel { start: 0., end: 1., steps: 8, step_type: StepType::Inclusive, ..Default::default() },
+        ColorChannel { start: 0., end: 1., steps: 8, step_type: StepType::Inclusive, ..Default::default() },
+    );
+
+    VisualizationSettings {
+        channel_settings,
+        ..Default::default()
+    }
+}
  
 fn update_visualization(
-    gizmos: Gizmos,
     mut commands: Commands,
     visualization_settings: ResMut<VisualizationSettings>,
     meshes: ResMut<Assets<Mesh>>,
     materials: ResMut<Assets<StandardMaterial>>,
     point_clouds: ResMut<Assets<PointCloud>>,
     point_cloud_materials: ResMut<Assets<PointCloudMaterial>>,
-    entities: Query<Entity, With<VisualizationMesh>>,
+    gizmo_assets: ResMut<Assets<GizmoAsset>>,
+    entities: Query<(Entity, &VisualizationMesh)>,
+    mut lattice_stats: ResMut<LatticeStats>,
 ) 
  {
 
      if visualization_settings.is_changed() {
  
          //Delete previous visualization 
-         for mesh in entities.iter(){
-             commands.entity(mesh).despawn();
+         for (mesh, slot) in entities.iter(){
+             if slot.0 == VisualizationSlot::Primary {
+                 commands.entity(mesh).despawn();
+             }
          }
   
-        spawn_3d_visualization(gizmos, commands, meshes, materials, point_clouds, point_cloud_materials, & *visualization_settings);
+        lattice_stats.delta_e = spawn_3d_visualization(commands, meshes, materials, point_clouds, point_cloud_materials, gizmo_assets, & *visualization_settings, &VisualizationTarget::primary());
     }
  }
 
 fn update_gizmo_config(
     mut config_store: ResMut<bevy::prelude::GizmoConfigStore>,
     visualization_settings: Res<VisualizationSettings>,
+    comparison: Res<ComparisonSettings>,
 ) {
     if visualization_settings.is_changed() {
         let (config, _handle) = config_store.config_mut::<bevy::prelude::DefaultGizmoConfigGroup>();
@@ -114,5 +186,13 @@ fn update_gizmo_config(
         config.line.joints = GizmoLineJoint::Miter;
 
     }
+
+    // The split view's second half draws its overlays with its own line width
+    if comparison.is_changed() {
+        let (config, _handle) = config_store.config_mut::<ComparisonGizmos>();
+        config.line.width = comparison.settings.line_width * 5.;
+        config.line.joints = GizmoLineJoint::Miter;
+        config.render_layers = RenderLayers::layer(COMPARISON_LAYER);
+    }
 }
 
@@ -0,0 +1,333 @@
+use std::fmt::Write as _;
+use std::path::Path;
+
+use bevy::prelude::*;
+
+use crate::color_models::{model_entry, COLOR_MODELS};
+use crate::colorimetry::{srgb_to_lab, srgb_to_oklab};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{alpha_values, channel_axes, get_point_and_color, Dimensionality};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+impl ReportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ReportFormat::Csv => "csv",
+            ReportFormat::Json => "json",
+        }
+    }
+
+    // Picked from the file extension, CSV unless it says json
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => ReportFormat::Json,
+            _ => ReportFormat::Csv,
+        }
+    }
+}
+
+// Path and outcome of the last export from the settings panel
+#[derive(Resource)]
+pub struct ReportExport {
+    pub path: String,
+    pub format: ReportFormat,
+    pub status: Option<String>,
+}
+
+impl Default for ReportExport {
+    fn default() -> Self {
+        Self {
+            path: "uniformity_report.csv".to_string(),
+            format: ReportFormat::Csv,
+            status: None,
+        }
+    }
+}
+
+impl ReportExport {
+    pub fn export(&mut self, settings: &VisualizationSettings) {
+        let path = Path::new(&self.path).with_extension(self.format.extension());
+        self.status = Some(match write_report(settings, &path) {
+            Ok(report) => format!("Wrote {} samples to {}", report.samples.len(), path.display()),
+            Err(error) => format!("Export failed: {error}"),
+        });
+    }
+}
+
+pub struct ReportSample {
+    pub alpha: f32,
+    pub channels: [f32; 3],
+    pub srgb: [f32; 3],
+    pub lab: Vec3,
+    pub oklab: Vec3,
+    // Distance to the next sample along each channel, None at the end of a linear axis
+    pub step_delta_e: [Option<f32>; 3],
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StepStats {
+    pub count: usize,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    pub std_dev: f32,
+}
+
+impl StepStats {
+    fn new(values: impl Iterator<Item = f32>) -> Self {
+        let values: Vec<f32> = values.collect();
+        if values.is_empty() {
+            return Self::default();
+        }
+        let count = values.len();
+        let mean = values.iter().sum::<f32>() / count as f32;
+        let variance = values.iter().map(|value| (value - mean).powi(2)).sum::<f32>() / count as f32;
+        Self {
+            count,
+            min: values.iter().copied().fold(f32::INFINITY, f32::min),
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean,
+            std_dev: variance.sqrt(),
+        }
+    }
+
+    // Spread relative to the mean step, zero for a perfectly even ramp
+    pub fn variation(&self) -> f32 {
+        if self.mean > 0. {self.std_dev / self.mean} else {0.}
+    }
+}
+
+pub struct UniformityReport {
+    pub model: String,
+    pub metric: &'static str,
+    pub channel_names: [&'static str; 3],
+    pub samples: Vec<ReportSample>,
+    pub channel_stats: [StepStats; 3],
+    pub overall: StepStats,
+}
+
+impl UniformityReport {
+    // Every sample of the current lattice with its step distances along each channel.
+    // The lattice is always the volume's, so the report doesn't change with the shape on screen.
+    pub fn new(settings: &VisualizationSettings) -> Self {
+        let axes = channel_axes(&VisualizationSettings { dimensionality: Dimensionality::Volume, ..settings.clone() });
+        let mut samples = Vec::new();
+
+        for alpha in alpha_values(settings) {
+            let evaluate = |index: [usize; 3]| -> ([f32; 3], [f32; 3]) {
+                let channels = [0, 1, 2].map(|axis| axes[axis].value(index[axis]));
+                let (_, color) = get_point_and_color((channels[0], channels[1], channels[2], alpha), settings);
+                let [r, g, b, _] = color.to_rgb().to_array();
+                (channels, [r, g, b])
+            };
+
+            for index_of_a in 0..axes[0].len() {
+                for index_of_b in 0..axes[1].len() {
+                    for index_of_c in 0..axes[2].len() {
+                        let index = [index_of_a, index_of_b, index_of_c];
+                        let (channels, srgb) = evaluate(index);
+
+                        let step_delta_e = [0, 1, 2].map(|axis| {
+                            let mut next = index;
+                            next[axis] = axes[axis].neighbor(index[axis], 1)?;
+                            Some(settings.delta_e_metric.delta_e(srgb, evaluate(next).1))
+                        });
+
+                        samples.push(ReportSample {
+                            alpha,
+                            channels,
+                            srgb,
+                            lab: srgb_to_lab(srgb),
+                            oklab: srgb_to_oklab(srgb),
+                            step_delta_e,
+                        });
+                    }
+                }
+            }
+        }
+
+        let channel_stats = [0, 1, 2].map(|axis| StepStats::new(samples.iter().filter_map(|sample| sample.step_delta_e[axis])));
+        let overall = StepStats::new(samples.iter().flat_map(|sample| sample.step_delta_e.into_iter().flatten()));
+
+        Self {
+            model: settings.model_name().to_string(),
+            metric: settings.delta_e_metric.name(),
+            channel_names: settings.model_channels().map(|info| info.name),
+            samples,
+            channel_stats,
+            overall,
+        }
+    }
+
+    // One row per sample, with the summary as leading comment lines
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# model: {}, metric: {}", self.model, self.metric);
+        for (name, stats) in self.summary() {
+            let _ = writeln!(
+                csv,
+                "# {name} steps: count {}, min {:.4}, max {:.4}, mean {:.4}, std dev {:.4}, variation {:.4}",
+                stats.count, stats.min, stats.max, stats.mean, stats.std_dev, stats.variation(),
+            );
+        }
+
+        let [a, b, c] = self.channel_names;
+        let _ = writeln!(
+            csv,
+            "alpha,{a},{b},{c},srgb_r,srgb_g,srgb_b,lab_l,lab_a,lab_b,oklab_l,oklab_a,oklab_b,delta_e_{a},delta_e_{b},delta_e_{c}",
+        );
+        for sample in &self.samples {
+            let step = |delta_e: Option<f32>| delta_e.map(|delta_e| format!("{delta_e:.6}")).unwrap_or_default();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                sample.alpha,
+                sample.channels[0], sample.channels[1], sample.channels[2],
+                sample.srgb[0], sample.srgb[1], sample.srgb[2],
+                sample.lab.x, sample.lab.y, sample.lab.z,
+                sample.oklab.x, sample.oklab.y, sample.oklab.z,
+                step(sample.step_delta_e[0]), step(sample.step_delta_e[1]), step(sample.step_delta_e[2]),
+            );
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        let number = |value: f32| if value.is_finite() {value.to_string()} else {"null".to_string()};
+        let triple = |values: [f32; 3]| format!("[{}, {}, {}]", number(values[0]), number(values[1]), number(values[2]));
+        let step = |delta_e: Option<f32>| delta_e.map(number).unwrap_or_else(|| "null".to_string());
+
+        let mut json = String::new();
+        let _ = writeln!(json, "{{");
+        let _ = writeln!(json, "  \"model\": {},", json_string(&self.model));
+        let _ = writeln!(json, "  \"metric\": {},", json_string(self.metric));
+        let _ = writeln!(json, "  \"channels\": [{}, {}, {}],", json_string(self.channel_names[0]), json_string(self.channel_names[1]), json_string(self.channel_names[2]));
+
+        let _ = writeln!(json, "  \"summary\": {{");
+        let summary = self.summary();
+        for (position, (name, stats)) in summary.iter().enumerate() {
+            let separator = if position + 1 < summary.len() {","} else {""};
+            let _ = writeln!(
+                json,
+                "    {}: {{\"count\": {}, \"min\": {}, \"max\": {}, \"mean\": {}, \"std_dev\": {}, \"variation\": {}}}{separator}",
+                json_string(name), stats.count, number(stats.min), number(stats.max), number(stats.mean), number(stats.std_dev), number(stats.variation()),
+            );
+        }
+        let _ = writeln!(json, "  }},");
+
+        let _ = writeln!(json, "  \"samples\": [");
+        for (position, sample) in self.samples.iter().enumerate() {
+            let separator = if position + 1 < self.samples.len() {","} else {""};
+            let _ = writeln!(
+                json,
+                "    {{\"alpha\": {}, \"channels\": {}, \"srgb\": {}, \"lab\": {}, \"oklab\": {}, \"delta_e\": [{}, {}, {}]}}{separator}",
+                number(sample.alpha),
+                triple(sample.channels),
+                triple(sample.srgb),
+                triple(sample.lab.to_array()),
+                triple(sample.oklab.to_array()),
+                step(sample.step_delta_e[0]), step(sample.step_delta_e[1]), step(sample.step_delta_e[2]),
+            );
+        }
+        let _ = writeln!(json, "  ]");
+        let _ = writeln!(json, "}}");
+        json
+    }
+
+    fn summary(&self) -> Vec<(&'static str, StepStats)> {
+        self.channel_names
+            .iter()
+            .copied()
+            .zip(self.channel_stats)
+            .chain([("all", self.overall)])
+            .collect()
+    }
+}
+
+// A JSON string literal, escaping what a custom model's names could contain
+fn json_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => literal.push_str("\\\""),
+            '\\' => literal.push_str("\\\\"),
+            '\n' => literal.push_str("\\n"),
+            '\r' => literal.push_str("\\r"),
+            '\t' => literal.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            },
+            c => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+// The lattice to report on headlessly: `--model <name>` picks a model by name, and
+// `--channels <start:end:steps>,<start:end:steps>,<start:end:steps>` sets each channel in the model's units
+pub fn apply_lattice_args(args: &[String], settings: &mut VisualizationSettings) -> Result<(), String> {
+    let value = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| {
+        args.get(index + 1).ok_or_else(|| format!("{flag} needs a value"))
+    });
+
+    if let Some(name) = value("--model") {
+        let name = name?;
+        let entry = COLOR_MODELS
+            .iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("unknown model '{name}', expected one of {}", COLOR_MODELS.iter().map(|entry| entry.name).collect::<Vec<_>>().join(", ")))?;
+        // Carry the current channel selection over to the new model's ranges, as the settings panel does
+        let previous = model_entry(settings.color_model).channels;
+        settings.channel_settings.0.remap_range(previous[0].range, entry.channels[0].range);
+        settings.channel_settings.1.remap_range(previous[1].range, entry.channels[1].range);
+        settings.channel_settings.2.remap_range(previous[2].range, entry.channels[2].range);
+        settings.color_model = entry.model;
+        settings.color_model_category = entry.category;
+    }
+
+    if let Some(channels) = value("--channels") {
+        let channels: Vec<&str> = channels?.split(',').collect();
+        let [a, b, c] = channels[..] else {return Err("--channels expects three start:end:steps triples".to_string())};
+        let targets = [&mut settings.channel_settings.0, &mut settings.channel_settings.1, &mut settings.channel_settings.2];
+        for (triple, channel) in [a, b, c].into_iter().zip(targets) {
+            let error = || format!("'{triple}' is not start:end:steps");
+            let parts: Vec<&str> = triple.split(':').collect();
+            let [start, end, steps] = parts[..] else {return Err(error())};
+            channel.start = start.trim().parse().map_err(|_| error())?;
+            channel.end = end.trim().parse().map_err(|_| error())?;
+            channel.steps = steps.trim().parse::<usize>().ok().filter(|steps| *steps > 0).ok_or_else(error)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Builds the report for `settings` and writes it in the format matching the path's extension
+pub fn write_report(settings: &VisualizationSettings, path: &Path) -> std::io::Result<UniformityReport> {
+    let report = UniformityReport::new(settings);
+    let contents = match ReportFormat::from_path(path) {
+        ReportFormat::Csv => report.to_csv(),
+        ReportFormat::Json => report.to_json(),
+    };
+    std::fs::write(path, contents)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_strings_escape_quotes_backslashes_and_controls() {
+        assert_eq!(json_string("Hue"), "\"Hue\"");
+        assert_eq!(json_string("my \"model\""), "\"my \\\"model\\\"\"");
+        assert_eq!(json_string("a\\b"), "\"a\\\\b\"");
+        assert_eq!(json_string("line\nbreak\u{1}"), "\"line\\nbreak\\u0001\"");
+    }
+}
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use prismatic_color::{Color as P_Color, ColorModel};
+
+use crate::annotations::WorldLabels;
+use crate::colorimetry::{linear_to_srgb, xyz_to_xyy, LINEAR_SRGB_TO_XYZ, XYZ_TO_LINEAR_SRGB};
+use crate::comparison::{ComparisonGizmos, ComparisonSettings};
+use crate::ui::VisualizationSettings;
+use crate::visualization::{place_color, VisualizationTarget, SCALE};
+
+// CIE 1931 2° standard observer color matching functions, 380nm to 780nm in 10nm steps
+const FIRST_WAVELENGTH: f32 = 380.;
+const WAVELENGTH_STEP: f32 = 10.;
+const CIE_1931_CMF: [[f32; 3]; 41] = [
+    [0.001368, 0.000039, 0.006450],
+    [0.004243, 0.000120, 0.020050],
+    [0.014310, 0.000396, 0.067850],
+    [0.043510, 0.001210, 0.207400],
+    [0.134380, 0.004000, 0.645600],
+    [0.283900, 0.011600, 1.385600],
+    [0.348280, 0.023000, 1.747060],
+    [0.336200, 0.038000, 1.772110],
+    [0.290800, 0.060000, 1.669200],
+    [0.195360, 0.090980, 1.287640],
+    [0.095640, 0.139020, 0.812950],
+    [0.032010, 0.208020, 0.465180],
+    [0.004900, 0.323000, 0.272000],
+    [0.009300, 0.503000, 0.158200],
+    [0.063270, 0.710000, 0.078250],
+    [0.165500, 0.862000, 0.042160],
+    [0.290400, 0.954000, 0.020300],
+    [0.433450, 0.994950, 0.008750],
+    [0.594500, 0.995000, 0.003900],
+    [0.762100, 0.952000, 0.002100],
+    [0.916300, 0.870000, 0.001650],
+    [1.026300, 0.757000, 0.001100],
+    [1.062200, 0.631000, 0.000800],
+    [1.002600, 0.503000, 0.000340],
+    [0.854450, 0.381000, 0.000190],
+    [0.642400, 0.265000, 0.000050],
+    [0.447900, 0.175000, 0.000020],
+    [0.283500, 0.107000, 0.000000],
+    [0.164900, 0.061000, 0.000000],
+    [0.087400, 0.032000, 0.000000],
+    [0.046770, 0.017000, 0.000000],
+    [0.022700, 0.008210, 0.000000],
+    [0.011359, 0.004102, 0.000000],
+    [0.005790, 0.002091, 0.000000],
+    [0.002899, 0.001047, 0.000000],
+    [0.001440, 0.000520, 0.000000],
+    [0.000690, 0.000249, 0.000000],
+    [0.000332, 0.000120, 0.000000],
+    [0.000166, 0.000060, 0.000000],
+    [0.000083, 0.000030, 0.000000],
+    [0.000042, 0.000015, 0.000000],
+];
+
+// Wavelengths that get a label along the locus
+const LABELED_WAVELENGTHS: [u32; 11] = [380, 460, 480, 500, 520, 540, 560, 580, 600, 620, 700];
+
+const PURPLE_LINE_SEGMENTS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LocusView {
+    // Flat xy chromaticity diagram on the floor of the scene
+    Chromaticity,
+    // Spectral colors placed like any other sample in the visualization space
+    ColorSpace,
+}
+
+fn wavelength(index: usize) -> f32 {
+    FIRST_WAVELENGTH + index as f32 * WAVELENGTH_STEP
+}
+
+// Spectral colors from 380nm to 780nm, then back along the purple line
+fn locus_xyz() -> Vec<(Option<u32>, Vec3)> {
+    let mut locus: Vec<(Option<u32>, Vec3)> = CIE_1931_CMF
+        .iter()
+        .enumerate()
+        .map(|(index, cmf)| (Some(wavelength(index) as u32), Vec3::from(*cmf)))
+        .collect();
+
+    // Straight line in xy between the two ends of the spectrum, at unit luminance
+    let violet = xyz_to_xyy(Vec3::from(CIE_1931_CMF[0]));
+    let red = xyz_to_xyy(Vec3::from(CIE_1931_CMF[CIE_1931_CMF.len() - 1]));
+    for segment in 1..=PURPLE_LINE_SEGMENTS {
+        let t = segment as f32 / PURPLE_LINE_SEGMENTS as f32;
+        let xy = red.lerp(violet, t);
+        locus.push((None, Vec3::new(xy.x / xy.y, 1., (1. - xy.x - xy.y) / xy.y)));
+    }
+    locus
+}
+
+// Brightest displayable version of a chromaticity, with out of gamut components kept signed
+fn normalized_linear_rgb(xyz: Vec3) -> Vec3 {
+    let linear = XYZ_TO_LINEAR_SRGB * xyz;
+    let peak = linear.max_element();
+    if peak > 0. {linear / peak} else {linear}
+}
+
+fn display_color(xyz: Vec3) -> Color {
+    let linear = normalized_linear_rgb(xyz).clamp(Vec3::ZERO, Vec3::ONE);
+    Color::linear_rgb(linear.x, linear.y, linear.z)
+}
+
+fn chromaticity_point(xyz: Vec3, settings: &VisualizationSettings) -> Vec3 {
+    let xyy = xyz_to_xyy(xyz);
+    Vec3::new(xyy.x, xyy.y, 0.) * SCALE * settings.viz_scale
+}
+
+fn color_space_point(xyz: Vec3, settings: &VisualizationSettings) -> Vec3 {
+    let [r, g, b] = normalized_linear_rgb(xyz).to_array().map(linear_to_srgb);
+    place_color(P_Color::from_tuple((r, g, b, 1.), ColorModel::RGBA), settings) * SCALE * settings.viz_scale
+}
+
+// Draws the spectral locus for each visualization on screen
+pub fn draw_spectral_locus(
+    mut gizmos: Gizmos,
+    mut comparison_gizmos: Gizmos<ComparisonGizmos>,
+    mut labels: ResMut<WorldLabels>,
+    settings: Res<VisualizationSettings>,
+    comparison: Res<ComparisonSettings>,
+) {
+    spectral_locus(&mut gizmos, &mut labels, &settings, &VisualizationTarget::primary());
+    if let Some((settings, target)) = comparison.shown() {
+        if target.on_comparison_camera() {
+            spectral_locus(&mut comparison_gizmos, &mut labels, settings, &target);
+        } else {
+            spectral_locus(&mut gizmos, &mut labels, settings, &target);
+        }
+    }
+}
+
+// The spectral locus and purple line, with the sRGB primaries triangle for comparison
+fn spectral_locus<Config: GizmoConfigGroup>(
+    gizmos: &mut Gizmos<'_, '_, Config>,
+    labels: &mut WorldLabels,
+    settings: &VisualizationSettings,
+    target: &VisualizationTarget,
+) {
+    if !settings.show_spectral_locus {return};
+
+    let place = |xyz: Vec3| match settings.locus_view {
+        LocusView::Chromaticity => chromaticity_point(xyz, settings),
+        LocusView::ColorSpace => color_space_point(xyz, settings),
+    } + target.offset;
+
+    let locus = locus_xyz();
+    let points: Vec<(Vec3, Color)> = locus.iter().map(|(_, xyz)| (place(*xyz), display_color(*xyz))).collect();
+    gizmos.linestrip_gradient(points.iter().copied());
+
+    for ((wavelength, _), (point, _)) in locus.iter().zip(&points) {
+        if let Some(wavelength) = wavelength.filter(|wavelength| LABELED_WAVELENGTHS.contains(wavelength)) {
+            labels.add(target, *point, format!("{wavelength}nm"));
+        }
+    }
+
+    // The gamut of our own primaries
+    let primaries = [Vec3::X, Vec3::Y, Vec3::Z].map(|primary| LINEAR_SRGB_TO_XYZ * primary);
+    let triangle = primaries.map(|xyz| (place(xyz), display_color(xyz)));
+    gizmos.linestrip_gradient(triangle.iter().copied().chain([triangle[0]]));
+}
@@ -1,27 +1,50 @@
-use bevy::{ecs::component::Component, prelude::{ResMut, Resource}, reflect::Reflect};
+use std::sync::Arc;
+
+use bevy::{ecs::component::Component, prelude::{Res, ResMut, Resource}, reflect::Reflect};
 use egui_double_slider::DoubleSlider;
 use prismatic_color::{ColorModel, ColorSpace};
 use bevy_egui::{
     egui::{self,RichText},EguiContextSettings, EguiContexts, EguiPlugin, EguiPrimaryContextPass, EguiStartupSet,
 };
 
-use crate::visualization::{ColorModelCategory, Dimensionality, VertexShape, RotationDirection, SlicingMethod};
-
-#[derive(Resource, Clone)]
+use crate::color_models::{model_entry, ChannelInfo, ColorModelCategory, PerceptualSpace, PositionSpace, ALPHA_CHANNEL, COLOR_MODELS, RGB_PRIMARIES};
+use crate::colorimetry::DeltaEMetric;
+use crate::comparison::{ComparisonMode, ComparisonSettings};
+use crate::contrast::{ContrastFilter, ContrastMetric};
+use crate::custom_model::{CustomColorModel, ExpressionModel, ExpressionSource};
+use crate::cvd::{CvdFilter, CvdModel, Deficiency};
+use crate::gradient::{GradientPaths, GradientSpace};
+use crate::hdr::{ExtendedRange, ToneMapping};
+use crate::isosurface::{IsoField, Isosurface};
+use crate::lut::{bake_remapping, LutSettings, LutView, EXPORT_SIZES};
+use crate::report::{ReportExport, ReportFormat};
+use crate::spectral::LocusView;
+use crate::visualization::{AlphaLayout, ColoringMode, DisplacementDisplay, Dimensionality, LatticeStats, VertexShape, RotationDirection, SlicingMethod};
+
+#[derive(Resource, Clone, PartialEq)]
 pub struct VisualizationSettings{
     pub viz_scale: f32,
     pub visualization_alpha: f32,
 
     pub component_limit: (f32,f32,f32),
+    pub extended_range: ExtendedRange,
+    pub lut: LutSettings,
     pub per_component_gamma: bool,
     pub gamma: (f32,f32,f32),
 
     pub channel_settings: (ColorChannel,ColorChannel,ColorChannel),
+    pub sweep_alpha: bool,
+    pub alpha_channel: ColorChannel,
+    pub alpha_layout: AlphaLayout,
 
     pub color_model_category: ColorModelCategory,
     pub color_model: ColorModel,
+    pub custom_source: ExpressionSource,
+    pub custom_model: Option<Arc<dyn CustomColorModel>>,
     pub color_space: ColorSpace,
     pub dimensionality: Dimensionality,
+    pub isosurface: Isosurface,
+    pub displacement_display: DisplacementDisplay,
 
     pub mesh_shape: VertexShape,
     pub instance_scale: f32,
@@ -30,19 +53,87 @@ pub struct VisualizationSettings{
     pub face_slicing: SlicingMethod,
     pub gamma_deform: bool,
     pub discrete_color: bool,
+    pub lit_shading: bool,
+    pub smooth_normals: bool,
+    // Quads split until their midpoints are within `subdivision_tolerance` of flat, in model units
+    pub adaptive_subdivision: bool,
+    pub subdivision_tolerance: f32,
+    pub subdivision_depth: usize,
+    pub wireframe: bool,
+    pub wireframe_color: [f32; 3],
+    pub wireframe_width: f32,
     pub color_space_model: ColorModel,
+    // Positions by a perceptual space or the custom model instead of `color_space_model` when set
+    pub position_space: Option<PositionSpace>,
+    pub coloring: ColoringMode,
+    pub delta_e_metric: DeltaEMetric,
+    pub cvd: CvdFilter,
+    pub contrast: ContrastFilter,
 
     // pub model_rotation: RotationDirection,
     pub model_mirrored: bool,
+    pub show_annotations: bool,
+    pub show_spectral_locus: bool,
+    pub locus_view: LocusView,
+    pub gradient: GradientPaths,
 
 }
 
-#[derive(Component, Debug, Clone, Reflect)]
+impl VisualizationSettings {
+    // Whether any sample can come out translucent
+    pub fn uses_alpha(&self) -> bool {
+        self.sweep_alpha || self.visualization_alpha < 1.
+    }
+
+    // Whether the volume is drawn as an isosurface instead of its outer shell
+    pub fn shows_isosurface(&self) -> bool {
+        self.dimensionality == Dimensionality::Volume && self.isosurface.enabled && self.displacement_display != DisplacementDisplay::ArrowsOnly
+    }
+
+    // The custom model, once compiled, while its category is selected
+    pub fn active_custom_model(&self) -> Option<&dyn CustomColorModel> {
+        if self.color_model_category != ColorModelCategory::Custom {
+            return None;
+        }
+        self.custom_model.as_deref()
+    }
+
+    pub fn model_name(&self) -> &str {
+        match self.active_custom_model() {
+            Some(model) => model.name(),
+            None => model_entry(self.color_model).name,
+        }
+    }
+
+    pub fn model_channels(&self) -> [ChannelInfo; 3] {
+        match self.active_custom_model() {
+            Some(model) => model.channels(),
+            None => model_entry(self.color_model).channels,
+        }
+    }
+
+    // Custom models output RGB, so the perceptual offset always addresses its primaries
+    pub fn model_primaries(&self) -> [&'static str; 3] {
+        match self.active_custom_model() {
+            Some(_) => RGB_PRIMARIES,
+            None => model_entry(self.color_model).primaries,
+        }
+    }
+}
+
+#[derive(Component, Debug, Clone, Reflect, PartialEq)]
 pub struct ColorChannel {
     pub start: f32,
     pub end: f32,
+    // Color steps, which also set the mesh resolution unless `mesh_steps` is given
     pub steps: usize,
+    pub mesh_steps: Option<usize>,
     pub step_type: StepType,
+    pub distribution: Distribution,
+    // Exponent for Power, strength for Logarithmic
+    pub curve: f32,
+    // Comma separated channel values used by Distribution::Stops
+    pub stops: String,
 }
 
 impl Default for ColorChannel {
@@ -51,19 +142,36 @@ impl Default for ColorChannel {
             start: 0.,
             end: 1.,
             steps: 8,
+            mesh_steps: None,
             step_type: StepType::Forward,
+            distribution: Distribution::Uniform,
+            curve: 2.,
+            stops: String::new(),
         }
     }
 }
 
 impl ColorChannel {
-    pub fn generate(&self, not_vertex: bool) -> Vec<ChannelIndex> {
+    pub fn generate(&self, not_vertex: bool, closed: bool) -> Vec<ChannelIndex> {
+
+        // Explicit stops replace the stepped sampling entirely
+        if self.distribution == Distribution::Stops {
+            let mut stops = self.parse_stops();
+            // The end of a closed ring is its start again
+            if closed && stops.last().is_some_and(|last| (last - self.end).abs() < 1e-4) {
+                stops.pop();
+            }
+            if !stops.is_empty() {
+                return stops.into_iter().map(|value| ChannelIndex {value}).collect();
+            }
+        }
 
-        let steps = if not_vertex {self.steps + 1} else {self.steps};
+        // A closed ring reuses its first sample in place of the end, so it never needs the extra one
+        let steps = if not_vertex && !closed {self.steps + 1} else {self.steps};
         
         let mut values = Vec::new();
 
-        let step_size = self.step_size(steps);
+        let divisions = if closed {self.steps as f32} else {self.divisions(steps)};
 
         let range = 
             match self.step_type {
@@ -73,19 +181,70 @@ impl ColorChannel {
             };
 
         for step in range {
-            let value = self.start + step as f32 * step_size;
+            let fraction = self.distribution.shape(step as f32 / divisions, self.curve);
+            let value = self.start + fraction * (self.end - self.start);
             
             values.push(ChannelIndex {value});
         }
         values
     }
 
-    fn step_size(&self, steps: usize) -> f32 {
+    // Samples at the mesh resolution, the color steps unless a separate one is set
+    pub fn generate_mesh(&self, not_vertex: bool, closed: bool) -> Vec<ChannelIndex> {
+        match self.mesh_steps {
+            Some(steps) => ColorChannel { steps, ..self.clone() }.generate(not_vertex, closed),
+            None => self.generate(not_vertex, closed),
+        }
+    }
+
+    // Every number typed into the stops field
+    fn typed_stops(&self) -> impl Iterator<Item = f32> + '_ {
+        self.stops
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter_map(|stop| stop.parse::<f32>().ok())
+            .filter(|stop| stop.is_finite())
+    }
+
+    fn in_range(&self, value: f32) -> bool {
+        value >= self.start.min(self.end) - 1e-4 && value <= self.start.max(self.end) + 1e-4
+    }
+
+    // Stops between start and end, in the order the channel sweeps, without repeats
+    pub fn parse_stops(&self) -> Vec<f32> {
+        let mut stops: Vec<f32> = self.typed_stops().filter(|stop| self.in_range(*stop)).collect();
+        stops.sort_by(|a, b| if self.end >= self.start {a.total_cmp(b)} else {b.total_cmp(a)});
+        stops.dedup_by(|a, b| (*a - *b).abs() < 1e-4);
+        stops
+    }
+
+    // Whether the channel sweeps at least `span`, i.e. a full turn of a cyclic channel
+    pub fn covers(&self, span: f32) -> bool {
+        (self.end - self.start).abs() >= span - 1e-4
+    }
+
+    // Stops left out of sampling for falling outside start..end
+    pub fn skipped_stops(&self) -> usize {
+        self.typed_stops().filter(|stop| !self.in_range(*stop)).count()
+    }
+
+    // Carry the selected portion of the channel, and any explicit stops, over to a new natural range
+    pub fn remap_range(&mut self, from: (f32, f32), to: (f32, f32)) {
+        let remap = |value: f32| to.0 + (value - from.0) / (from.1 - from.0) * (to.1 - to.0);
+        self.start = remap(self.start);
+        self.end = remap(self.end);
+
+        let stops: Vec<String> = self.typed_stops().map(|stop| format!("{}", (remap(stop) * 1e4).round() / 1e4)).collect();
+        if !stops.is_empty() {
+            self.stops = stops.join(", ");
+        }
+    }
+
+    fn divisions(&self, steps: usize) -> f32 {
         if self.step_type == StepType::Inclusive {
-            (self.end - self.start) / (steps as f32 - 1.)
+            steps as f32 - 1.
         }
         else {
-            (self.end - self.start) / (steps as f32)
+            steps as f32
         }
     }
 }
@@ -102,6 +261,27 @@ pub enum StepType {
     Inclusive,
 }
 
+#[derive(Component, Debug, Clone, Reflect, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Power,
+    Logarithmic,
+    Smoothstep,
+    Stops,
+}
+
+impl Distribution {
+    // Reshape an evenly spaced fraction of the channel range
+    fn shape(&self, fraction: f32, curve: f32) -> f32 {
+        match self {
+            Distribution::Uniform | Distribution::Stops => fraction,
+            Distribution::Power => fraction.powf(curve),
+            Distribution::Logarithmic => (1. + curve * fraction).ln() / (1. + curve).ln(),
+            Distribution::Smoothstep => fraction * fraction * (3. - 2. * fraction),
+        }
+    }
+}
+
 impl Default for VisualizationSettings{
     fn default() -> Self {
         Self {
@@ -109,6 +289,8 @@ impl Default for VisualizationSettings{
             visualization_alpha: 1.,
 
             component_limit: (1., 1., 1.), 
+            extended_range: ExtendedRange::default(),
+            lut: LutSettings::default(),
             per_component_gamma: false,
             gamma: (2.2, 2.2, 2.2),
 
@@ -117,10 +299,17 @@ impl Default for VisualizationSettings{
                 ColorChannel::default(),
                 ColorChannel::default(),
             ),
+            sweep_alpha: false,
+            alpha_channel: ColorChannel { start: 0.25, end: 1., steps: 4, step_type: StepType::Inclusive, ..Default::default() },
+            alpha_layout: AlphaLayout::Grid,
 
             color_model_category: ColorModelCategory::Spherical,
             color_model: ColorModel::SphericalHCLA,
+            custom_source: ExpressionSource::default(),
+            custom_model: ExpressionModel::parse(&ExpressionSource::default()).ok().map(|model| Arc::new(model) as Arc<dyn CustomColorModel>),
             dimensionality: Dimensionality::Vertex,
+            isosurface: Isosurface::default(),
+            displacement_display: DisplacementDisplay::Off,
             
             mesh_shape: VertexShape::Sphere,
             instance_scale: 1.0,
@@ -129,187 +318,579 @@ impl Default for VisualizationSettings{
             face_slicing: SlicingMethod::Y,
             gamma_deform: false,
             discrete_color: true,
+            lit_shading: false,
+            smooth_normals: true,
+            adaptive_subdivision: false,
+            subdivision_tolerance: 0.005,
+            subdivision_depth: 3,
+            wireframe: false,
+            wireframe_color: [0.2, 0.2, 0.2],
+            wireframe_width: 1.5,
             color_space: ColorSpace::XYZ,
             color_space_model: ColorModel::RGBA,
+            position_space: None,
+            coloring: ColoringMode::TrueColor,
+            delta_e_metric: DeltaEMetric::Ciede2000,
+            cvd: CvdFilter::default(),
+            contrast: ContrastFilter::default(),
 
             // model_rotation: RotationDirection::None,
             model_mirrored: false,
+            show_annotations: false,
+            show_spectral_locus: false,
+            locus_view: LocusView::Chromaticity,
+            gradient: GradientPaths::default(),
         }
     }
 }
 
-pub fn ui_overlay(mut contexts: EguiContexts, mut settings: ResMut<VisualizationSettings>) {
+pub fn ui_overlay(mut contexts: EguiContexts, mut visualization_settings: ResMut<VisualizationSettings>, mut comparison_settings: ResMut<ComparisonSettings>, stats: Res<LatticeStats>, mut report: ResMut<ReportExport>) {
+    // The widgets edit copies, written back only when something moved so the lattices aren't rebuilt every frame
+    let mut settings = visualization_settings.clone();
+    let mut comparison = comparison_settings.clone();
+
+    let ctx = contexts.ctx_mut().unwrap();
 
     //Create window for variable sliders
     egui::Window::new("Settings")
         .resizable(true)
-        .show(contexts.ctx_mut().unwrap(), | mut ui|{
+        .show(ctx, | ui|{
 
-        let width = ui.available_width();
+        settings_panel(ui, &mut settings, Some(&stats));
 
-        ui.label("Scale");
-        ui.add(egui::Slider::new( &mut settings.viz_scale ,0.0..=2.0).text("Visualization Scale"));
         ui.separator();
 
-        ui.label("Perceptual Offset");
-        ui.add(egui::Slider::new( &mut settings.component_limit.0 ,0.0..=1.0).text("Red"));
-        ui.add(egui::Slider::new( &mut settings.component_limit.1 ,0.0..=1.0).text("Green"));
-        ui.add(egui::Slider::new( &mut settings.component_limit.2 ,0.0..=1.0).text("Blue"));
-
+        ui.label("Comparison");
         ui.horizontal(|ui| {
-            ui.label("Gamma");
-            ui.checkbox(&mut settings.per_component_gamma, "per component");
+            ui.selectable_value(&mut comparison.mode, ComparisonMode::Off, "Off");
+            ui.selectable_value(&mut comparison.mode, ComparisonMode::Split, "Split View");
+            ui.selectable_value(&mut comparison.mode, ComparisonMode::Overlay, "Overlay");
         });
-        if settings.per_component_gamma {
-            ui.add(egui::Slider::new( &mut settings.gamma.0 ,0.1..=3.0).text("Red"));
-            ui.add(egui::Slider::new( &mut settings.gamma.1 ,0.1..=3.0).text("Green"));
-            ui.add(egui::Slider::new( &mut settings.gamma.2 ,0.1..=3.0).text("Blue"));
+        if comparison.mode == ComparisonMode::Overlay {
+            ui.add(egui::Slider::new( &mut comparison.overlay_offset ,0.0..=5.0).text("Offset"));
         }
-        else {
-            ui.add(egui::Slider::new( &mut settings.gamma.0 ,0.1..=3.0));
-            settings.gamma.1 = settings.gamma.0;
-            settings.gamma.2 = settings.gamma.0;
+        if comparison.mode != ComparisonMode::Off && ui.button("Copy Settings To Comparison").clicked() {
+            comparison.settings = settings.clone();
         }
 
         ui.separator();
 
+        ui.label("Uniformity Report");
         ui.horizontal(|ui| {
-            ui.label("Channel Settings");
+            ui.selectable_value(&mut report.format, ReportFormat::Csv, "CSV");
+            ui.selectable_value(&mut report.format, ReportFormat::Json, "JSON");
+            ui.text_edit_singleline(&mut report.path);
+        });
+        if ui.button("Export Report").clicked() {
+            report.export(&settings);
+        }
+        if let Some(status) = &report.status {
+            ui.label(status);
+        }
+
+        ui.separator();
+
+        ui.label("WASD - Horizontal Movement");
+        ui.label("Ctrl & Space - Vertical Movement");
+        ui.label("Arrow Keys - Camera Rotation");
+
+    });
+
+    // The second settings set gets its own panel
+    if comparison.mode != ComparisonMode::Off {
+        egui::Window::new("Comparison Settings")
+            .resizable(true)
+            .show(ctx, | ui|{
+            settings_panel(ui, &mut comparison.settings, None);
         });
+    }
+
+    visualization_settings.set_if_neq(settings);
+    comparison_settings.set_if_neq(comparison);
+}
 
-        //Channel A
-        ui_channel(&mut ui, "A", &mut settings.channel_settings.0, width);
+fn settings_panel(mut ui: &mut egui::Ui, settings: &mut VisualizationSettings, stats: Option<&LatticeStats>) {
 
-        //Channel B
-        ui_channel(&mut ui, "B", &mut settings.channel_settings.1, width);
+    let width = ui.available_width();
 
-        //Channel C
-        ui_channel(&mut ui, "C", &mut settings.channel_settings.2, width);
+    ui.label("Scale");
+    ui.add(egui::Slider::new( &mut settings.viz_scale ,0.0..=2.0).text("Visualization Scale"));
+    ui.add(egui::Slider::new( &mut settings.visualization_alpha ,0.0..=1.0).text("Opacity"));
+    ui.separator();
 
-        ui.separator();
+    let previous_channels = settings.model_channels();
+    let primaries = settings.model_primaries();
 
-        ui.label("Color Model");
+    let previous_range = settings.extended_range;
+    ui.checkbox(&mut settings.extended_range.enabled, "Extended Range (HDR)");
+    if settings.extended_range.enabled {
+        ui.add(egui::Slider::new( &mut settings.extended_range.peak ,1.0..=4.0).text("Peak"));
+        ui.checkbox(&mut settings.extended_range.linear_light, "Model Output Is Linear Light");
         ui.horizontal(|ui| {
-             egui::ComboBox::from_label("")
-            .selected_text(format!("{:?}", settings.color_space_model))
-            .show_ui(ui, |ui| {
-                 ui.selectable_value(&mut settings.color_model_category, ColorModelCategory::Spherical, "Spherical");
-                ui.selectable_value(&mut settings.color_model_category, ColorModelCategory::Cubic, "Cubic");
-                ui.selectable_value(&mut settings.color_model_category, ColorModelCategory::LumaChroma, "Luma-Chroma");
-            });
+            ui.label("Tone Mapping");
+            for tone_mapping in ToneMapping::ALL {
+                ui.selectable_value(&mut settings.extended_range.tone_mapping, tone_mapping, tone_mapping.name());
+            }
         });
+    }
 
-        ui.separator();
+    // Leaving extended range, or lowering its peak, brings channels and offsets back inside the new limits
+    if settings.extended_range != previous_range {
+        let extended_range = settings.extended_range;
+        let channels = settings.model_channels();
+        extended_range.clamp_channel(&mut settings.channel_settings.0, &channels[0]);
+        extended_range.clamp_channel(&mut settings.channel_settings.1, &channels[1]);
+        extended_range.clamp_channel(&mut settings.channel_settings.2, &channels[2]);
+        let limit_max = extended_range.component_limit_max();
+        settings.component_limit.0 = settings.component_limit.0.min(limit_max);
+        settings.component_limit.1 = settings.component_limit.1.min(limit_max);
+        settings.component_limit.2 = settings.component_limit.2.min(limit_max);
+    }
+    ui.separator();
+
+    let limit_max = settings.extended_range.component_limit_max();
+    ui.label("Perceptual Offset");
+    ui.add(egui::Slider::new( &mut settings.component_limit.0 ,0.0..=limit_max).text(primaries[0]));
+    ui.add(egui::Slider::new( &mut settings.component_limit.1 ,0.0..=limit_max).text(primaries[1]));
+    ui.add(egui::Slider::new( &mut settings.component_limit.2 ,0.0..=limit_max).text(primaries[2]));
+
+    ui.horizontal(|ui| {
+        ui.label("Gamma");
+        ui.checkbox(&mut settings.per_component_gamma, "per component");
+    });
+    if settings.per_component_gamma {
+        ui.add(egui::Slider::new( &mut settings.gamma.0 ,0.1..=3.0).text(primaries[0]));
+        ui.add(egui::Slider::new( &mut settings.gamma.1 ,0.1..=3.0).text(primaries[1]));
+        ui.add(egui::Slider::new( &mut settings.gamma.2 ,0.1..=3.0).text(primaries[2]));
+    }
+    else {
+        ui.add(egui::Slider::new( &mut settings.gamma.0 ,0.1..=3.0));
+        settings.gamma.1 = settings.gamma.0;
+        settings.gamma.2 = settings.gamma.0;
+    }
+
+    ui.separator();
+
+    ui.horizontal(|ui| {
+        ui.label("Channel Settings");
+    });
+
+    let channels = settings.model_channels().map(|info| settings.extended_range.channel_info(&info));
+    // Only surfaces are built on a lattice apart from their colors
+    let meshed = settings.dimensionality == Dimensionality::Face || settings.dimensionality == Dimensionality::Volume;
+
+    //Channel A
+    ui_channel(&mut ui, &channels[0], &mut settings.channel_settings.0, width, meshed);
 
+    //Channel B
+    ui_channel(&mut ui, &channels[1], &mut settings.channel_settings.1, width, meshed);
+
+    //Channel C
+    ui_channel(&mut ui, &channels[2], &mut settings.channel_settings.2, width, meshed);
+
+    //Alpha
+    ui.checkbox(&mut settings.sweep_alpha, "Sweep Alpha");
+    if settings.sweep_alpha {
+        ui_channel(&mut ui, &ALPHA_CHANNEL, &mut settings.alpha_channel, width, false);
         ui.horizontal(|ui| {
-            match settings.color_model_category {
-            ColorModelCategory::Spherical => {
-                ui.selectable_value(&mut settings.color_model, ColorModel::SphericalHCLA, "HCL");
-            },
-            ColorModelCategory::Cubic => {
-                ui.selectable_value(&mut settings.color_model, ColorModel::CubicHSVA, "HSV");
-                ui.selectable_value(&mut settings.color_model, ColorModel::CubicHSLA, "HSL");
-            },
-            ColorModelCategory::LumaChroma => {
-                ui.selectable_value(&mut settings.color_model, ColorModel::YUVA, "YUV");
-            },
-        }});
-        
+            ui.selectable_value(&mut settings.alpha_layout, AlphaLayout::Grid, "Grid");
+            ui.selectable_value(&mut settings.alpha_layout, AlphaLayout::Layered, "Layered");
+        });
+    }
+
+    ui.separator();
+
+    ui.label("Color Model");
+    ui.horizontal(|ui| {
+         egui::ComboBox::from_id_salt("color_model_category")
+        .selected_text(settings.color_model_category.name())
+        .show_ui(ui, |ui| {
+            for category in ColorModelCategory::ALL {
+                ui.selectable_value(&mut settings.color_model_category, category, category.name());
+            }
+        });
+    });
+
+    // Keep the selected model inside the selected category
+    if model_entry(settings.color_model).category != settings.color_model_category {
+        if let Some(entry) = settings.color_model_category.models().next() {
+            settings.color_model = entry.model;
+        }
+    }
+
+    ui.separator();
 
+    ui.horizontal(|ui| {
+        for entry in settings.color_model_category.models() {
+            ui.selectable_value(&mut settings.color_model, entry.model, entry.name);
+        }
+    });
+
+    if settings.color_model_category == ColorModelCategory::Custom {
+        ui_custom_model(&mut ui, settings);
+    }
+
+    // Channel ranges are stored in natural units, so follow the model when it changes,
+    // whether a built in model, the custom category or a recompiled custom model
+    let next_channels = settings.model_channels();
+    if (0..3).any(|axis| next_channels[axis].range != previous_channels[axis].range) {
+        settings.channel_settings.0.remap_range(previous_channels[0].range, next_channels[0].range);
+        settings.channel_settings.1.remap_range(previous_channels[1].range, next_channels[1].range);
+        settings.channel_settings.2.remap_range(previous_channels[2].range, next_channels[2].range);
+    }
+
+    ui.separator();
+
+    let current_color_model = settings.color_model;
+
+    ui.label("Color Space");
+    let selected_space = match settings.position_space {
+        Some(PositionSpace::Perceptual(space)) => space.name(),
+        Some(PositionSpace::Custom) => "Custom Model",
+        None => model_entry(settings.color_space_model).name,
+    };
+    egui::ComboBox::from_id_salt("color_space_model")
+    .selected_text(selected_space)
+    .show_ui(ui, |ui| {
+        let is_model = settings.position_space.is_none();
+        if ui.selectable_label(is_model && settings.color_space_model == current_color_model, "Current Color Model").clicked() {
+            settings.color_space_model = current_color_model;
+            settings.position_space = None;
+        }
+        for entry in COLOR_MODELS {
+            if ui.selectable_label(is_model && settings.color_space_model == entry.model, format!("{} {}", entry.category.name(), entry.name)).clicked() {
+                settings.color_space_model = entry.model;
+                settings.position_space = None;
+            }
+        }
         ui.separator();
+        for space in PerceptualSpace::ALL {
+            ui.selectable_value(&mut settings.position_space, Some(PositionSpace::Perceptual(space)), space.name());
+        }
+        if settings.custom_model.is_some() {
+            ui.separator();
+            ui.selectable_value(&mut settings.position_space, Some(PositionSpace::Custom), "Custom Model");
+        }
+    });
 
-        let current_color_model = settings.color_model;
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut settings.color_space, ColorSpace::XYZ, "XYZ");
+        ui.selectable_value(&mut settings.color_space, ColorSpace::Cylindrical, "Cylindrical");
+    });
 
-        ui.label("Color Space");
-        egui::ComboBox::from_label("")
-        .selected_text(format!("{:?}", settings.color_space_model))
-        .show_ui(ui, |ui| {
-            ui.selectable_value(&mut settings.color_space_model, current_color_model, "Current Color Model");
-            ui.selectable_value(&mut settings.color_space_model, ColorModel::RGBA, "RGB");
-            ui.selectable_value(&mut settings.color_space_model, ColorModel::CMYA, "CMY");
-            ui.selectable_value(&mut settings.color_space_model, ColorModel::SphericalHCLA, "Spherical HCL");
-            ui.selectable_value(&mut settings.color_space_model, ColorModel::CubicHSVA, "Cubic HSV");
-            ui.selectable_value(&mut settings.color_space_model, ColorModel::YUVA, "YUV");
+    ui.horizontal(|ui| {
+
+        // Perceptual and custom positioning don't go through the model space that gets mirrored
+        let mirrorable = settings.position_space.is_none();
+        ui.add_enabled(mirrorable, egui::Checkbox::new(&mut settings.model_mirrored, "Mirror"))
+            .on_disabled_hover_text("Mirroring applies to model positioning only");
+        ui.checkbox(&mut settings.show_annotations, "Axes & Labels");
+        ui.checkbox(&mut settings.show_spectral_locus, "Spectral Locus");
+
+    });
+
+    if settings.show_spectral_locus {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.locus_view, LocusView::Chromaticity, "xyY Diagram");
+            ui.selectable_value(&mut settings.locus_view, LocusView::ColorSpace, "In Color Space");
         });
+    }
 
+    ui.checkbox(&mut settings.gradient.enabled, "Gradient Paths");
+    if settings.gradient.enabled {
+        ui.horizontal(|ui| {
+            ui.label("From");
+            ui.color_edit_button_rgb(&mut settings.gradient.start);
+            ui.label("To");
+            ui.color_edit_button_rgb(&mut settings.gradient.end);
+            ui.add(egui::DragValue::new(&mut settings.gradient.stops).range(2..=32).prefix("Stops: "));
+        });
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut settings.color_space, ColorSpace::XYZ, "XYZ");
-            ui.selectable_value(&mut settings.color_space, ColorSpace::Cylindrical, "Cylindrical");
+            for space in GradientSpace::ALL {
+                let mut shown = settings.gradient.spaces.contains(&space);
+                if ui.checkbox(&mut shown, space.name()).changed() {
+                    let spaces = &settings.gradient.spaces;
+                    settings.gradient.spaces = GradientSpace::ALL
+                        .into_iter()
+                        .filter(|other| if *other == space {shown} else {spaces.contains(other)})
+                        .collect();
+                }
+            }
         });
+    }
+
+    ui.separator();
 
+    ui.label("Shape");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut settings.dimensionality, Dimensionality::Vertex, "Vertex");
+        ui.selectable_value(&mut settings.dimensionality, Dimensionality::Edge, "Edge");
+        ui.selectable_value(&mut settings.dimensionality, Dimensionality::Face, "Face");
+        ui.selectable_value(&mut settings.dimensionality, Dimensionality::Volume, "Volume");
+    });
+  
+    match settings.dimensionality {
+        Dimensionality::Vertex => {
+            // ui.label("Mesh Shape");
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new( &mut settings.instance_scale ,0.0..=2.0).text("Shape Scale"));
+            });
+        },
+        Dimensionality::Edge => {
+            ui.label("Edge Direction");
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Y, "X|Axial");
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::X, "Y|Radial");
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Z, "Z|Concentric");
+                ui.add(egui::Slider::new( &mut settings.line_width ,0.0..=10.0).text("Line Width"));
+                ui.checkbox(&mut settings.discrete_color, "Discrete Color");
+            });
+        },
+        Dimensionality::Face => {
+            ui.label("Quad Direction");
+            ui.horizontal(|ui| {
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::X, "X|Axial");
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Y, "Y|Radial");
+                ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Z, "Z|Concentric");
+                ui.checkbox(&mut settings.discrete_color, "Discrete Color");
+            });
+
+        },
+        Dimensionality::Volume => {
+            ui.horizontal(|ui| {
+                ui.add_enabled(!settings.isosurface.enabled, egui::Checkbox::new(&mut settings.discrete_color, "Discrete Color"))
+                    .on_disabled_hover_text("The isosurface is colored continuously");
+                ui.checkbox(&mut settings.isosurface.enabled, "Isosurface");
+            });
+            if settings.isosurface.enabled {
+                ui_isosurface(ui, settings);
+            }
+        },
+    }
+
+    if settings.dimensionality == Dimensionality::Face || settings.dimensionality == Dimensionality::Volume {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.lit_shading, "Lit Shading");
+            if settings.lit_shading {
+                ui.checkbox(&mut settings.smooth_normals, "Smooth Normals");
+            }
+            else {
+                ui.label("Accurate Color");
+            }
+        });
         ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.adaptive_subdivision, "Adaptive Subdivision");
+            if settings.adaptive_subdivision {
+                ui.add(egui::Slider::new( &mut settings.subdivision_tolerance ,0.0005..=0.05).logarithmic(true).text("Tolerance"));
+                ui.add(egui::Slider::new( &mut settings.subdivision_depth ,1..=5).text("Max Depth"));
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut settings.wireframe, "Wireframe");
+            if settings.wireframe {
+                ui.color_edit_button_rgb(&mut settings.wireframe_color);
+                ui.add(egui::Slider::new( &mut settings.wireframe_width ,0.5..=6.0).text("Width"));
+            }
+        });
+    }
+
+    ui.separator();
+
+    ui.label("Remapping Displacement");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut settings.displacement_display, DisplacementDisplay::Off, "Off");
+        ui.selectable_value(&mut settings.displacement_display, DisplacementDisplay::WithShape, "With Shape");
+        ui.selectable_value(&mut settings.displacement_display, DisplacementDisplay::ArrowsOnly, "Arrows Only");
+    });
+
+    ui.separator();
+
+    ui.label("Coloring");
+    // Isosurface vertices fall between lattice samples, so they have no neighbor ΔE
+    let measures_delta_e = !settings.shows_isosurface();
+    if !measures_delta_e && settings.coloring == ColoringMode::DeltaE {
+        settings.coloring = ColoringMode::TrueColor;
+    }
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut settings.coloring, ColoringMode::TrueColor, "True Color");
+        ui.add_enabled_ui(measures_delta_e, |ui| ui.selectable_value(&mut settings.coloring, ColoringMode::DeltaE, "ΔE Heatmap"))
+            .inner
+            .on_disabled_hover_text("Not available on the isosurface");
+        ui.selectable_value(&mut settings.coloring, ColoringMode::Contrast, "Contrast");
+    });
 
-            ui.checkbox(&mut settings.model_mirrored, "Mirror");
+    if settings.coloring == ColoringMode::DeltaE {
+        ui.horizontal(|ui| {
+            for metric in [DeltaEMetric::Ciede2000, DeltaEMetric::Cie76, DeltaEMetric::OkLab] {
+                ui.selectable_value(&mut settings.delta_e_metric, metric, metric.name());
+            }
+        });
+        ui.label("Distance to the next sample along the slicing axis");
+        if let Some(delta_e) = stats.and_then(|stats| stats.delta_e) {
+            ui.label(format!("Min {:.3}   Max {:.3}   Mean {:.3}", delta_e.min, delta_e.max, delta_e.mean));
+        }
+    }
 
+    if settings.coloring == ColoringMode::Contrast || settings.contrast.hide_failing {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.contrast.metric, ContrastMetric::Wcag2, ContrastMetric::Wcag2.name());
+            ui.selectable_value(&mut settings.contrast.metric, ContrastMetric::Apca, ContrastMetric::Apca.name());
+            ui.label("Background");
+            ui.color_edit_button_rgb(&mut settings.contrast.reference);
+        });
+        ui.horizontal(|ui| {
+            ui.label("Required");
+            let metric = settings.contrast.metric;
+            for (level, threshold) in metric.thresholds().into_iter().enumerate() {
+                ui.selectable_value(&mut settings.contrast.level, level, metric.threshold_label(threshold));
+            }
         });
+    }
+    ui.checkbox(&mut settings.contrast.hide_failing, "Hide Samples Failing Contrast");
 
-        ui.separator();
+    ui.separator();
 
-        ui.label("Shape");
+    ui.label("1D/3D LUT (.cube)");
+    ui.horizontal(|ui| {
+        ui.text_edit_singleline(&mut settings.lut.path);
+        if ui.button("Load").clicked() {
+            settings.lut.load();
+        }
+    });
+    if let Some(status) = &settings.lut.status {
+        ui.label(status);
+    }
+    if settings.lut.lut.is_some() {
         ui.horizontal(|ui| {
-            ui.selectable_value(&mut settings.dimensionality, Dimensionality::Vertex, "Vertex");
-            ui.selectable_value(&mut settings.dimensionality, Dimensionality::Edge, "Edge");
-            ui.selectable_value(&mut settings.dimensionality, Dimensionality::Face, "Face");
-            ui.selectable_value(&mut settings.dimensionality, Dimensionality::Volume, "Volume");
+            ui.checkbox(&mut settings.lut.enabled, "Apply LUT");
+            ui.selectable_value(&mut settings.lut.view, LutView::Transformed, "Transformed Solid");
+            ui.selectable_value(&mut settings.lut.view, LutView::Displacement, "Displacement");
         });
-  
-        match settings.dimensionality {
-            Dimensionality::Vertex => {
-                // ui.label("Mesh Shape");
-                ui.horizontal(|ui| {
-                    ui.add(egui::Slider::new( &mut settings.instance_scale ,0.0..=2.0).text("Shape Scale"));
-                });
-            },
-            Dimensionality::Edge => {
-                ui.label("Edge Direction");
-                ui.horizontal(|ui| {
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Y, "X|Axial");
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::X, "Y|Radial");
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Z, "Z|Concentric");
-                    ui.add(egui::Slider::new( &mut settings.line_width ,0.0..=10.0).text("Line Width"));
-                    ui.checkbox(&mut settings.discrete_color, "Discrete Color");
-                });
-            },
-            Dimensionality::Face => {
-                ui.label("Quad Direction");
-                ui.horizontal(|ui| {
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::X, "X|Axial");
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Y, "Y|Radial");
-                    ui.selectable_value(&mut settings.face_slicing, SlicingMethod::Z, "Z|Concentric");
-                    ui.checkbox(&mut settings.discrete_color, "Discrete Color");
-                });
-
-            },
-            Dimensionality::Volume => {
-                ui.checkbox(&mut settings.discrete_color, "Discrete Color");
-            },
+    }
+
+    ui.label("Export Perceptual Offset As LUT");
+    ui.horizontal(|ui| {
+        for size in EXPORT_SIZES {
+            ui.selectable_value(&mut settings.lut.export_size, size, size.to_string());
         }
+        ui.text_edit_singleline(&mut settings.lut.export_path);
+    });
+    if ui.button("Export LUT").clicked() {
+        let baked = bake_remapping(settings, settings.lut.export_size);
+        settings.lut.save_export(&baked);
+    }
+    if let Some(status) = &settings.lut.export_status {
+        ui.label(status);
+    }
 
-        ui.separator();
+    ui.separator();
+
+    ui.label("Color Vision Deficiency");
+    ui.horizontal(|ui| {
+        for deficiency in Deficiency::ALL {
+            ui.selectable_value(&mut settings.cvd.deficiency, deficiency, deficiency.name());
+        }
+    });
 
-        // ui.label("Additional Settings");
+    if settings.cvd.deficiency != Deficiency::None {
+        ui.horizontal(|ui| {
+            ui.selectable_value(&mut settings.cvd.model, CvdModel::Machado, "Machado");
+            ui.selectable_value(&mut settings.cvd.model, CvdModel::Brettel, "Brettel");
+        });
+        ui.add(egui::Slider::new( &mut settings.cvd.severity ,0.0..=1.0).text("Severity"));
+        ui.checkbox(&mut settings.cvd.affect_positions, "Plot Simulated Color");
+    }
 
-        ui.checkbox(&mut settings.gamma_deform, "Gamma Deform");
+    ui.separator();
 
-        // ui.separator();
+    // ui.label("Additional Settings");
 
-        ui.label("WASD - Horizontal Movement");
-        ui.label("Ctrl & Space - Vertical Movement");
-        ui.label("Arrow Keys - Camera Rotation");
+    ui.checkbox(&mut settings.gamma_deform, "Gamma Deform");
+
+}
 
+// Field and level of the surface marched through the volume
+fn ui_isosurface(ui: &mut egui::Ui, settings: &mut VisualizationSettings) {
+    let metric = settings.delta_e_metric;
+    ui.horizontal(|ui| {
+        for field in IsoField::ALL {
+            if ui.selectable_label(settings.isosurface.field == field, field.name()).clicked() {
+                settings.isosurface.set_field(field, metric);
+            }
+        }
     });
+    if settings.isosurface.field == IsoField::DeltaE {
+        ui.horizontal(|ui| {
+            for metric in [DeltaEMetric::Ciede2000, DeltaEMetric::Cie76, DeltaEMetric::OkLab] {
+                ui.selectable_value(&mut settings.delta_e_metric, metric, metric.name());
+            }
+            ui.label("Reference");
+            ui.color_edit_button_rgb(&mut settings.isosurface.reference);
+        });
+    }
+    let (min, max) = settings.isosurface.field.range(settings.delta_e_metric);
+    ui.add(egui::Slider::new(&mut settings.isosurface.level, min..=max).text("Level"));
+}
 
+// Expression editor for the custom color model, compiled on demand
+fn ui_custom_model(ui: &mut egui::Ui, settings: &mut VisualizationSettings) {
+    ui.label("Linear RGB from channels x, y, z");
+    for (output, source) in ["r", "g", "b"].iter().zip(settings.custom_source.forward.iter_mut()) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{output} ="));
+            ui.text_edit_singleline(source);
+        });
+    }
+
+    ui.label("Channels from linear r, g, b");
+    for (output, source) in ["x", "y", "z"].iter().zip(settings.custom_source.inverse.iter_mut()) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{output} ="));
+            ui.text_edit_singleline(source);
+        });
+    }
+
+    ui.horizontal(|ui| {
+        ui.checkbox(&mut settings.custom_source.cyclic_x, "x Is Hue");
+        if ui.button("Compile").clicked() {
+            if let Some(model) = settings.custom_source.compile() {
+                settings.custom_model = Some(model);
+            }
+        }
+    });
+    if let Some(status) = &settings.custom_source.status {
+        ui.label(status);
+    }
 }
 
-fn ui_channel(ui: &mut egui::Ui, label: &str, channel: &mut ColorChannel, width: f32) {
+fn ui_channel(ui: &mut egui::Ui, info: &ChannelInfo, channel: &mut ColorChannel, width: f32, meshed: bool) {
+    // Stops fix the samples themselves, so there is no step count to set or refine
+    let steppable = channel.distribution != Distribution::Stops;
+
     // Steps
     ui.horizontal(|ui| {
-        ui.label(label);
-        ui.add(
+        ui.label(info.label());
+        ui.add_enabled(
+            steppable,
             egui::DragValue::new(&mut channel.steps)
                 .range(1..=24)
                 .prefix("Steps: "),
-        );
+        ).on_disabled_hover_text("Stops set the samples directly");
+
+        if !meshed {return};
+        let mut separate_mesh = channel.mesh_steps.is_some();
+        let response = ui.add_enabled(steppable, egui::Checkbox::new(&mut separate_mesh, "Separate Mesh"))
+            .on_disabled_hover_text("Stops set the mesh as well as the colors");
+        if response.changed() {
+            channel.mesh_steps = separate_mesh.then_some(channel.steps * 4);
+        }
+        if let (true, Some(mesh_steps)) = (steppable, &mut channel.mesh_steps) {
+            ui.add(
+                egui::DragValue::new(mesh_steps)
+                    .range(1..=128)
+                    .prefix("Mesh: "),
+            );
+        }
     });
 
     // Step type
@@ -324,13 +905,39 @@ fn ui_channel(ui: &mut egui::Ui, label: &str, channel: &mut ColorChannel, width:
         }
     });
 
+    // Distribution
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut channel.distribution, Distribution::Uniform, "Uniform");
+        ui.selectable_value(&mut channel.distribution, Distribution::Power, "Power");
+        ui.selectable_value(&mut channel.distribution, Distribution::Logarithmic, "Log");
+        ui.selectable_value(&mut channel.distribution, Distribution::Smoothstep, "Smoothstep");
+        ui.selectable_value(&mut channel.distribution, Distribution::Stops, "Stops");
+    });
+
+    match channel.distribution {
+        Distribution::Power => {
+            ui.add(egui::Slider::new(&mut channel.curve, 0.1..=5.0).text("Exponent"));
+        },
+        Distribution::Logarithmic => {
+            ui.add(egui::Slider::new(&mut channel.curve, 0.1..=100.0).logarithmic(true).text("Strength"));
+        },
+        Distribution::Stops => {
+            ui.add(egui::TextEdit::singleline(&mut channel.stops).hint_text("0.05, 0.15, 0.3, ..."));
+            let skipped = channel.skipped_stops();
+            if skipped > 0 {
+                ui.label(format!("{skipped} stops outside the start/end range are skipped"));
+            }
+        },
+        Distribution::Uniform | Distribution::Smoothstep => {},
+    }
+
     // Start/End slider
     let mut start = channel.start;
     let mut end = channel.end;
 
     ui.horizontal(|ui| {
         ui.add(
-            DoubleSlider::new(&mut start, &mut end, 0.0..=1.0)
+            DoubleSlider::new(&mut start, &mut end, info.range.0..=info.range.1)
                 .width(width)
                 .separation_distance(0.0),
         );
@@ -1,6 +1,8 @@
+use std::collections::HashMap;
+
 use bevy::{color, gizmos::gizmos, render::render_asset::RenderAssetUsages};
 use bevy_egui::egui::emath::OrderedFloat;
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use prismatic_color::{Color as P_Color, ColorModel, ColorSpace, IntoColor, };
 
 use bevy_pointcloud::{point_cloud, PointCloudPlugin};
@@ -12,14 +14,56 @@ use bevy_pointcloud::render::PointCloudRenderMode;
 use bevy::{
     prelude::{*},
     render::render_resource::PrimitiveTopology,
-    render::mesh::Indices,
+    render::mesh::{Indices, VertexAttributeValues},
+    render::view::RenderLayers,
 };
 
-use crate::ui::VisualizationSettings;
+use crate::camera::PrimaryCamera;
+use crate::color_models::{ChannelInfo, PositionSpace};
+use crate::colorimetry::{linear_to_srgb, srgb_to_linear};
+use crate::isosurface::isosurface_mesh;
+use crate::ui::{ChannelIndex, ColorChannel, VisualizationSettings};
 
 // A marker component for our components so we can query them separately from the ground plane
 #[derive(Component)]
-pub struct VisualizationMesh;
+pub struct VisualizationMesh(pub VisualizationSlot);
+
+// Which settings set a visualization was built from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisualizationSlot {
+    Primary,
+    Comparison,
+}
+
+// Where a visualization is spawned: its slot, the render layers that see it and a world offset
+pub struct VisualizationTarget {
+    pub slot: VisualizationSlot,
+    pub layers: RenderLayers,
+    pub offset: Vec3,
+}
+
+impl VisualizationTarget {
+    pub fn primary() -> Self {
+        Self {
+            slot: VisualizationSlot::Primary,
+            layers: RenderLayers::default(),
+            offset: Vec3::ZERO,
+        }
+    }
+
+    // Whether only the comparison camera of the split view sees it
+    pub fn on_comparison_camera(&self) -> bool {
+        !self.layers.intersects(&RenderLayers::default())
+    }
+
+    fn bundle(&self) -> impl Bundle {
+        (
+            VisualizationMesh(self.slot),
+            self.layers.clone(),
+            Transform::from_translation(self.offset),
+        )
+    }
+}
 
 
 
@@ -30,15 +74,6 @@ pub enum RotationDirection {
     Counterclockwise,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum ColorModelCategory {
-    Spherical,
-    Cubic,
-    LumaChroma,
-}
-
-
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum SlicingMethod {
     X,
@@ -95,6 +130,78 @@ impl SlicingMethod {
 
 }
 
+// Quad or triangle centers of a blended mesh, in mesh space, used to re-sort its triangles
+#[derive(Component)]
+pub struct TranslucentQuads {
+    centers: Vec<Vec3>,
+    // Index buffer entries of each center's primitive, `stride` per center
+    indices: Vec<u32>,
+    stride: usize,
+}
+
+impl TranslucentQuads {
+    // Sorts a triangle list mesh one triangle at a time
+    fn triangles(mesh: &Mesh) -> Option<Self> {
+        let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {return None};
+        let Some(Indices::U32(indices)) = mesh.indices() else {return None};
+        let centers = indices
+            .chunks_exact(3)
+            .map(|triangle| triangle.iter().map(|i| Vec3::from(positions[*i as usize])).sum::<Vec3>() / 3.)
+            .collect();
+        Some(Self { centers, indices: indices.clone(), stride: 3 })
+    }
+}
+
+// Rewrites the index buffer of each translucent mesh so its farthest primitives draw first
+pub fn sort_translucent_quads(
+    cameras: Query<Ref<GlobalTransform>, With<PrimaryCamera>>,
+    translucent_meshes: Query<(Ref<TranslucentQuads>, &Mesh3d, &GlobalTransform)>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let Some(camera) = cameras.iter().next() else {return};
+
+    for (quads, mesh, transform) in &translucent_meshes {
+        if !camera.is_changed() && !quads.is_added() {continue};
+        let Some(mesh) = meshes.get_mut(&mesh.0) else {continue};
+
+        let eye = transform.affine().inverse().transform_point3(camera.translation());
+        let mut order: Vec<usize> = (0..quads.centers.len()).collect();
+        order.sort_by(|a, b| {
+            eye.distance_squared(quads.centers[*b]).total_cmp(&eye.distance_squared(quads.centers[*a]))
+        });
+
+        let indices: Vec<u32> = order
+            .iter()
+            .flat_map(|primitive| quads.indices[primitive * quads.stride..(primitive + 1) * quads.stride].iter().copied())
+            .collect();
+        mesh.insert_indices(Indices::U32(indices));
+    }
+}
+
+// How layers of a swept alpha channel are placed relative to each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlphaLayout {
+    Grid,
+    Layered,
+}
+
+impl AlphaLayout {
+    // Model space offset of one alpha layer
+    pub fn offset(&self, layer: usize, layer_count: usize) -> Vec3 {
+        match self {
+            AlphaLayout::Grid => {
+                let spacing = 2.5;
+                Vec3::X * spacing * (layer as f32 - (layer_count as f32 - 1.) / 2.)
+            },
+            // Shells overlap almost entirely and blend into one stack, nudged apart along Z so coincident faces don't z-fight
+            AlphaLayout::Layered => {
+                let spacing = 0.15;
+                Vec3::Z * spacing * (layer as f32 - (layer_count as f32 - 1.) / 2.)
+            },
+        }
+    }
+}
+
 #[derive(Clone,PartialEq)]
 pub enum VertexShape {
     Sphere,
@@ -135,6 +242,27 @@ pub enum DimensionList {
 }
 
 impl DimensionList {
+    fn vertices(&self) -> indexmap::map::Keys<'_, VertexObject, usize> {
+        match self {
+            DimensionList::Vertex(vertex_list) => vertex_list.vertex_registry().keys(),
+            DimensionList::Edge(edge_list) => edge_list.vertex_registry().keys(),
+            DimensionList::Face(face_list) | DimensionList::Volume(face_list) => face_list.vertex_registry().keys(),
+        }
+    }
+
+    // Spread of the neighbor distances, only measured in the ΔE coloring mode
+    pub fn delta_e_stats(&self) -> Option<DeltaEStats> {
+        let values: Vec<f32> = self.vertices().filter_map(|vertex| vertex.delta_e).map(|delta_e| delta_e.into_inner()).collect();
+        if values.is_empty() {
+            return None;
+        }
+        Some(DeltaEStats {
+            min: values.iter().copied().fold(f32::INFINITY, f32::min),
+            max: values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            mean: values.iter().sum::<f32>() / values.len() as f32,
+        })
+    }
+
     pub fn render(
         &self,
         commands: &mut Commands,
@@ -142,9 +270,15 @@ impl DimensionList {
         materials: &mut Assets<StandardMaterial>,
         point_clouds: &mut Assets<PointCloud>,
         point_cloud_material: &mut Assets<PointCloudMaterial>,
-        gizmos: &mut Gizmos,
+        gizmo_assets: &mut Assets<GizmoAsset>,
         settings: &VisualizationSettings,
+        target: &VisualizationTarget,
     ) {
+        let display_colors = DisplayColors {
+            settings,
+            delta_e_range: self.delta_e_stats().map_or((0., 0.), |stats| (stats.min, stats.max)),
+        };
+
         match self {
             DimensionList::Vertex(vertex_list) => {
                 // Render vertices as a point cloud
@@ -153,11 +287,7 @@ impl DimensionList {
                     .map(|(vertex_object, _)| {
                         let position: Vec3 =
                             vertex_object.point.clone().into_vec3() * SCALE * settings.viz_scale;
-                        let color = P_Color::from_array(
-                            vertex_object.color.map(|x| x.into_inner()),
-                            settings.color_model,
-                        )
-                        .to_bevy_color()
+                        let color = display_colors.color(vertex_object)
                         .to_srgba()
                         .to_f32_array();
                         PointCloudData {
@@ -179,37 +309,55 @@ impl DimensionList {
                     commands.spawn((
                         PointCloud3d(point_cloud),
                         PointCloudMaterial3d(my_material.clone()),
-                        VisualizationMesh,
+                        target.bundle(),
                     )); 
              },
              DimensionList::Edge(edge_list) => {
-                // Render edges with gizmo lines
+                // Render edges as one retained gizmo
+                let mut lines = GizmoAsset::new();
                 for edge in &edge_list.edges {
                     let vertex_1 = edge_list.vertex_registry.get_index(edge.0).unwrap().0;
                     let vertex_2 = edge_list.vertex_registry.get_index(edge.1).unwrap().0;
                     
                     if settings.discrete_color {
-                        gizmos.line(vertex_1.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_2.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_1.color.into_color(settings));
+                        lines.line(vertex_1.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_2.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), display_colors.color(vertex_1));
                     } 
                     else {
-                        gizmos.line_gradient(vertex_1.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_2.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_1.color.into_color(settings), vertex_2.color.into_color(settings));
+                        lines.line_gradient(vertex_1.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), vertex_2.point.map(|axis| axis.into_inner() * SCALE * settings.viz_scale).into(), display_colors.color(vertex_1), display_colors.color(vertex_2));
                     }
                 }
+
+                commands.spawn((
+                    Gizmo {
+                        handle: gizmo_assets.add(lines),
+                        line_config: GizmoLineConfig {
+                            width: settings.line_width * 5.,
+                            joints: GizmoLineJoint::Miter,
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    target.bundle(),
+                ));
             },       
-            DimensionList::Face(face_list) => {
+            DimensionList::Face(face_list) | DimensionList::Volume(face_list) => {
                 //Render faces with a triangle based mesh
                 // Collect positions, normals, and colors
                 let mut positions: Vec<[f32; 3]> = Vec::new();
                 let mut normals: Vec<[f32; 3]> = Vec::new();
                 let mut colors: Vec<[f32; 4]> = Vec::new();
                 let mut indices: Vec<u32> = Vec::new();
+                let mut centers: Vec<Vec3> = Vec::new();
+
+                let smooth_normals = (settings.lit_shading && settings.smooth_normals).then(|| face_list.smooth_normals());
 
-                for (i1, i2, i3, i4) in &face_list.faces {
+                for ((i1, i2, i3, i4), source) in face_list.faces.iter().zip(&face_list.color_sources) {
                     // Lookup vertices from registry
                     let v1 = face_list.vertex_registry.get_index(*i1).unwrap().0;
                     let v2 = face_list.vertex_registry.get_index(*i2).unwrap().0;
                     let v3 = face_list.vertex_registry.get_index(*i3).unwrap().0;
                     let v4 = face_list.vertex_registry.get_index(*i4).unwrap().0;
+                    let color_source = face_list.vertex_registry.get_index(*source).unwrap().0;
 
                     let verts = [v1, v2, v3, v4];
 
@@ -221,14 +369,12 @@ impl DimensionList {
                         positions.push(v.point.map(|p| p.into_inner() * SCALE * settings.viz_scale));
                         let color = 
                             if settings.discrete_color {
-                                P_Color::from_array(v1.color.map(|x| x.into_inner()), settings.color_model)
-                                .to_bevy_color()
+                                display_colors.color(color_source)
                                 .to_linear()
                                 .to_f32_array()
                             }
                             else {
-                                P_Color::from_array(v.color.map(|x| x.into_inner()), settings.color_model)
-                                .to_bevy_color()
+                                display_colors.color(v)
                                 .to_linear()
                                 .to_f32_array()
                         };
@@ -240,9 +386,11 @@ impl DimensionList {
                     let p2 = Vec3::from(positions[base as usize + 1]);
                     let p3 = Vec3::from(positions[base as usize + 2]);
                     let normal = (p2 - p1).cross(p3 - p1).normalize_or_zero();
-                    for _ in 0..4 {
+                    for index in [i1, i2, i3, i4] {
+                        let normal = smooth_normals.as_ref().map_or(normal, |smooth| smooth[*index]);
                         normals.push(normal.into());
                     }
+                    centers.push((p1 + p3) / 2.);
 
                     // Add indices for two triangles: (0,1,2) and (0,2,3)
                     indices.extend_from_slice(&[
@@ -259,107 +407,62 @@ impl DimensionList {
                 mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
                 mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
                 mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-                mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
-
-                // Unlit so vertex colors are shown directly
-                let material = materials.add(StandardMaterial {
-                    base_color: Color::WHITE,
-                    unlit: true,
-                    cull_mode: None,
-                    ..default()
-                });
+                mesh.insert_indices(bevy::render::mesh::Indices::U32(indices.clone()));
 
-                commands.spawn((
-                     
+                let material = materials.add(surface_material(settings));
+
+                let mut entity = commands.spawn((
                     Mesh3d( meshes.add(mesh)),
                     MeshMaterial3d(material),
-                    VisualizationMesh,
+                    target.bundle(),
                 ));
-            },
-            DimensionList::Volume(face_list) => {
-                //Render faces with a triangle based mesh
-                // Collect positions, normals, and colors
-                let mut positions: Vec<[f32; 3]> = Vec::new();
-                let mut normals: Vec<[f32; 3]> = Vec::new();
-                let mut colors: Vec<[f32; 4]> = Vec::new();
-                let mut indices: Vec<u32> = Vec::new();
-
-                for (i1, i2, i3, i4) in &face_list.faces {
-                    // Lookup vertices from registry
-                    let v1 = face_list.vertex_registry.get_index(*i1).unwrap().0;
-                    let v2 = face_list.vertex_registry.get_index(*i2).unwrap().0;
-                    let v3 = face_list.vertex_registry.get_index(*i3).unwrap().0;
-                    let v4 = face_list.vertex_registry.get_index(*i4).unwrap().0;
-
-                    let verts = [v1, v2, v3, v4];
-
-                    // Push positions/colors
 
-                    
-                    let base = positions.len() as u32;
-                    for v in &verts {
-                        positions.push(v.point.map(|p| p.into_inner() * SCALE * settings.viz_scale));
-                        let color = 
-                            if settings.discrete_color {
-                                P_Color::from_array(v1.color.map(|x| x.into_inner()), settings.color_model)
-                                .to_bevy_color()
-                                .to_linear()
-                                .to_f32_array()
-                            }
-                            else {
-                                P_Color::from_array(v.color.map(|x| x.into_inner()), settings.color_model)
-                                .to_bevy_color()
-                                .to_linear()
-                                .to_f32_array()
-                        };
-                        colors.push(color);
-                    }
+                // Blended quads are drawn back to front, so keep them ordered as the camera moves
+                if settings.uses_alpha() {
+                    entity.insert(TranslucentQuads { centers, indices, stride: 6 });
+                }
 
-                    // Compute a simple normal (cross product of two edges)
-                    let p1 = Vec3::from(positions[base as usize]);
-                    let p2 = Vec3::from(positions[base as usize + 1]);
-                    let p3 = Vec3::from(positions[base as usize + 2]);
-                    let normal = (p2 - p1).cross(p3 - p1).normalize_or_zero();
-                    for _ in 0..4 {
-                        normals.push(normal.into());
+                // Quad outlines kept as their own retained gizmo on top of the fill
+                if settings.wireframe {
+                    let [r, g, b] = settings.wireframe_color;
+                    let color = Color::linear_rgb(r, g, b);
+                    let mut wireframe = GizmoAsset::new();
+                    for (i1, i2) in face_list.outline_edges() {
+                        let p1 = face_list.vertex_registry.get_index(i1).unwrap().0.point.into_vec3() * SCALE * settings.viz_scale;
+                        let p2 = face_list.vertex_registry.get_index(i2).unwrap().0.point.into_vec3() * SCALE * settings.viz_scale;
+                        wireframe.line(p1, p2, color);
                     }
 
-                    // Add indices for two triangles: (0,1,2) and (0,2,3)
-                    indices.extend_from_slice(&[
-                        base, base + 1, base + 2,
-                        base, base + 2, base + 3,
-                    ]);
+                    commands.spawn((
+                        Gizmo {
+                            handle: gizmo_assets.add(wireframe),
+                            line_config: GizmoLineConfig {
+                                width: settings.wireframe_width,
+                                ..default()
+                            },
+                            depth_bias: -0.001,
+                        },
+                        target.bundle(),
+                    ));
                 }
-
-                // Build mesh
-                let mut mesh = Mesh::new(
-                    bevy::render::mesh::PrimitiveTopology::TriangleList,
-                    RenderAssetUsages::default(), // or RenderAssetUsages::RENDER_WORLD if you only need rendering
-                );
-                mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-                mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
-                mesh.insert_indices(bevy::render::mesh::Indices::U32(indices));
-
-                // Unlit so vertex colors are shown directly
-                let material = materials.add(StandardMaterial {
-                    base_color: Color::WHITE,
-                    unlit: true,
-                    cull_mode: None,
-                    ..default()
-                });
-
-                commands.spawn((
-                     
-                    Mesh3d( meshes.add(mesh)),
-                    MeshMaterial3d(material),
-                    VisualizationMesh,
-                ));
             },
         }
     }
 }
 
+// Unlit keeps vertex colors exact, lit trades accuracy for a readable shape
+fn surface_material(settings: &VisualizationSettings) -> StandardMaterial {
+    StandardMaterial {
+        base_color: Color::WHITE,
+        unlit: !settings.lit_shading,
+        double_sided: settings.lit_shading,
+        perceptual_roughness: 0.9,
+        cull_mode: None,
+        alpha_mode: if settings.uses_alpha() {AlphaMode::Blend} else {AlphaMode::Opaque},
+        ..default()
+    }
+}
+
 trait IntoVec3 {
     fn into_vec3(self) -> Vec3;
 }
@@ -371,28 +474,99 @@ impl IntoVec3 for [OrderedFloat<f32>; 3] {
     }
 }
 
-trait OrderedArrayIntoColor {
-    fn into_color(self, settings: &VisualizationSettings) -> Color;
+// Resolves the color each vertex is drawn with
+struct DisplayColors<'a> {
+    settings: &'a VisualizationSettings,
+    delta_e_range: (f32, f32),
 }
 
-impl OrderedArrayIntoColor for [OrderedFloat<f32>; 4] {
-    fn into_color(self, settings: &VisualizationSettings) -> Color {
-        P_Color::from_array(self.map(|x| x.into_inner()), settings.color_model).to_bevy_color()
+impl DisplayColors<'_> {
+    // An RGBA sample as it appears on screen, before any diagnostic coloring
+    fn true_color(&self, rgba: [f32; 4]) -> Color {
+        self.settings.cvd.simulate_bevy_color(self.settings.extended_range.display_color(rgba))
     }
+
+    fn color(&self, vertex: &VertexObject) -> Color {
+        self.sample_color(vertex.color.map(|x| x.into_inner()), vertex.delta_e.map(|delta_e| delta_e.into_inner()))
+    }
+
+    // An RGBA sample in the active coloring mode, true color under the heatmap when it has no ΔE
+    fn sample_color(&self, rgba: [f32; 4], delta_e: Option<f32>) -> Color {
+        let color = self.true_color(rgba);
+        match (self.settings.coloring, delta_e) {
+            (ColoringMode::DeltaE, Some(delta_e)) => {
+                let (min, max) = self.delta_e_range;
+                let t = if max > min {(delta_e - min) / (max - min)} else {0.};
+                heat_color(t).with_alpha(color.alpha())
+            },
+            (ColoringMode::Contrast, _) => {
+                let srgba = color.to_srgba();
+                self.settings.contrast.band_color([srgba.red, srgba.green, srgba.blue]).with_alpha(srgba.alpha)
+            },
+            _ => color,
+        }
+    }
+}
+
+// Viridis sampled at five stops, low values dark purple and high values yellow
+fn heat_color(t: f32) -> Color {
+    const STOPS: [[f32; 3]; 5] = [
+        [0.267, 0.005, 0.329],
+        [0.231, 0.322, 0.545],
+        [0.129, 0.569, 0.549],
+        [0.369, 0.788, 0.384],
+        [0.993, 0.906, 0.144],
+    ];
+    let position = t.clamp(0., 1.) * (STOPS.len() - 1) as f32;
+    let index = (position.floor() as usize).min(STOPS.len() - 2);
+    let fraction = position - index as f32;
+    let [r, g, b] = [0, 1, 2].map(|channel| STOPS[index][channel] + (STOPS[index + 1][channel] - STOPS[index][channel]) * fraction);
+    Color::srgb(r, g, b)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColoringMode {
+    TrueColor,
+    // Distance to the next lattice sample along the slicing axis
+    DeltaE,
+    // Contrast level reached against the reference color
+    Contrast,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DeltaEStats {
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+}
+
+// Summary of the most recent primary visualization, shown in the settings panel
+#[derive(Resource, Default)]
+pub struct LatticeStats {
+    pub delta_e: Option<DeltaEStats>,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct VertexObject {
     pub point: [OrderedFloat<f32>;3],
+    // RGBA, whichever model the sample came from
     pub color: [OrderedFloat<f32>;4],
+    pub delta_e: Option<OrderedFloat<f32>>,
 }
 
 impl VertexObject {
-    fn new(point: [f32;3], color: P_Color) -> VertexObject {
-        VertexObject { point: point.map(OrderedFloat::from), color: color.to_array().map(OrderedFloat::from) }
+    // Straight line blend towards `other`, for points that have to sit on a segment rather than the surface
+    fn lerp(&self, other: &VertexObject, t: f32) -> VertexObject {
+        let mix = |a: OrderedFloat<f32>, b: OrderedFloat<f32>| OrderedFloat(a.into_inner() + (b.into_inner() - a.into_inner()) * t);
+        VertexObject {
+            point: [0, 1, 2].map(|axis| mix(self.point[axis], other.point[axis])),
+            color: [0, 1, 2, 3].map(|channel| mix(self.color[channel], other.color[channel])),
+            delta_e: self.delta_e.zip(other.delta_e).map(|(a, b)| mix(a, b)),
+        }
     }
-    fn from_tuple(point_color: ([f32;3], P_Color)) -> VertexObject {
-        VertexObject::new(point_color.0,point_color.1)
+
+    fn new(point: [f32;3], color: P_Color, delta_e: Option<f32>) -> VertexObject {
+        VertexObject { point: point.map(OrderedFloat::from), color: color.to_rgb().to_array().map(OrderedFloat::from), delta_e: delta_e.map(OrderedFloat::from) }
     }
 }
 
@@ -414,6 +588,8 @@ pub struct EdgeList {
 pub struct FaceList {
     vertex_registry: IndexMap<VertexObject, usize>,
     faces: Vec<(usize, usize, usize, usize)>,
+    // Vertex whose color fills each face under discrete color, the first corner of its lattice quad
+    color_sources: Vec<usize>,
 }
 
 // Common trait for vertex management
@@ -486,6 +662,7 @@ impl FaceList {
         Self {
             vertex_registry: IndexMap::new(),
             faces: Vec::new(),
+            color_sources: Vec::new(),
         }
     }
 
@@ -495,16 +672,59 @@ impl FaceList {
         v2: VertexObject,
         v3: VertexObject,
         v4: VertexObject,
+    ) -> usize {
+        let color_source = v1.clone();
+        self.add_quad_colored_by(v1, v2, v3, v4, &color_source)
+    }
+
+    // A quad that takes its discrete color from `color_source` rather than its own first corner
+    pub fn add_quad_colored_by(
+        &mut self,
+        v1: VertexObject,
+        v2: VertexObject,
+        v3: VertexObject,
+        v4: VertexObject,
+        color_source: &VertexObject,
     ) -> usize {
         let i1 = self.get_or_insert_index(&v1);
         let i2 = self.get_or_insert_index(&v2);
         let i3 = self.get_or_insert_index(&v3);
         let i4 = self.get_or_insert_index(&v4);
+        let source = self.get_or_insert_index(color_source);
         self.faces.push((i1, i2, i3, i4));
+        self.color_sources.push(source);
         self.faces.len() - 1
     }
 }
 
+impl FaceList {
+    // Each quad side once, leaving out the diagonal the triangulation adds
+    fn outline_edges(&self) -> IndexSet<(usize, usize)> {
+        let mut edges = IndexSet::new();
+        for (i1, i2, i3, i4) in &self.faces {
+            for (a, b) in [(i1, i2), (i2, i3), (i3, i4), (i4, i1)] {
+                edges.insert((*a.min(b), *a.max(b)));
+            }
+        }
+        edges
+    }
+
+    // Per registry vertex normals averaged over every quad that shares the vertex
+    fn smooth_normals(&self) -> Vec<Vec3> {
+        let mut normals = vec![Vec3::ZERO; self.vertex_registry.len()];
+        for (i1, i2, i3, i4) in &self.faces {
+            let p1 = self.vertex_registry.get_index(*i1).unwrap().0.point.into_vec3();
+            let p2 = self.vertex_registry.get_index(*i2).unwrap().0.point.into_vec3();
+            let p3 = self.vertex_registry.get_index(*i3).unwrap().0.point.into_vec3();
+            let normal = (p2 - p1).cross(p3 - p1).normalize_or_zero();
+            for index in [i1, i2, i3, i4] {
+                normals[*index] += normal;
+            }
+        }
+        normals.iter().map(|normal| normal.normalize_or_zero()).collect()
+    }
+}
+
 impl VertexCollection for FaceList {
 
     fn vertex_registry(&self) -> &IndexMap<VertexObject, usize> {
@@ -519,40 +739,237 @@ impl VertexCollection for FaceList {
 
 pub const SCALE: f32 = 5.0;
 
-trait BevyColorConvert {
-    fn to_bevy_color(&self) -> Color;
-}
-
-impl BevyColorConvert for P_Color {
-    fn to_bevy_color(&self) -> Color {
-        let color = self.to_rgb().to_array();
-        Color::srgba(color[0], color[1], color[2], color[3])
-    }
-}
-
 pub fn spawn_3d_visualization(
-    mut gizmos: Gizmos,
     mut commands: Commands,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     mut point_clouds: ResMut<Assets<PointCloud>>,
     mut point_cloud_materials: ResMut<Assets<PointCloudMaterial>>,
-    settings: &VisualizationSettings)
+    mut gizmo_assets: ResMut<Assets<GizmoAsset>>,
+    settings: &VisualizationSettings,
+    target: &VisualizationTarget) -> Option<DeltaEStats>
 {
-    generate_dimension_lists(settings)
-        .render(&mut commands, &mut meshes, &mut materials, &mut point_clouds ,&mut point_cloud_materials, &mut gizmos, settings);
+    let dim_list = generate_dimension_lists(settings);
+    if settings.lut.shows_displacement() {
+        let colors = DisplayColors { settings, delta_e_range: (0., 0.) };
+        let arrows = lattice_displacements(settings, |base_color| {
+            let (from, to, color) = lut_displacement(base_color, settings);
+            (from, to, colors.true_color(color.to_rgb().to_array()))
+        });
+        spawn_arrows(&mut commands, &mut gizmo_assets, settings, target, &arrows);
+    }
+    else if settings.shows_isosurface() {
+        // Surface vertices sit between lattice samples, so there is no neighbor ΔE to color them by
+        let colors = DisplayColors { settings, delta_e_range: (0., 0.) };
+        let mesh = isosurface_mesh(settings, |color| colors.sample_color(color.to_rgb().to_array(), None));
+        let translucent = settings.uses_alpha().then(|| TranslucentQuads::triangles(&mesh)).flatten();
+        let mut entity = commands.spawn((
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(materials.add(surface_material(settings))),
+            target.bundle(),
+        ));
+        if let Some(translucent) = translucent {
+            entity.insert(translucent);
+        }
+    }
+    else if settings.displacement_display != DisplacementDisplay::ArrowsOnly {
+        dim_list.render(&mut commands, &mut meshes, &mut materials, &mut point_clouds ,&mut point_cloud_materials, &mut gizmo_assets, settings, target);
+    }
+
+    if settings.displacement_display != DisplacementDisplay::Off {
+        let mut arrows = lattice_displacements(settings, |base_color| {
+            let colors = sample_colors(base_color, settings);
+            // Both ends placed like the shape's samples, so the arrows start or end on them
+            (position_color(colors.raw, settings), position_color(colors.transformed, settings), Color::WHITE)
+        });
+        // Longest displacement at the top of the heat ramp
+        let longest = arrows.iter().map(|(from, to, _)| from.distance(*to)).fold(0., f32::max);
+        for (from, to, color) in &mut arrows {
+            *color = heat_color(if longest > 0. {from.distance(*to) / longest} else {0.});
+        }
+        spawn_arrows(&mut commands, &mut gizmo_assets, settings, target, &arrows);
+    }
+
+    dim_list.delta_e_stats()
 }
 
-fn wrap_index(index: usize, offset: usize, length: usize) -> usize {
-    let raw = index + offset;
-    return raw % length;
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisplacementDisplay {
+    Off,
+    // Arrows from raw to remapped positions drawn over the shape
+    WithShape,
+    ArrowsOnly,
 }
 
+// Model space endpoints returned by `endpoints` for every lattice sample, alpha layers laid out like the shape
+fn lattice_displacements(
+    settings: &VisualizationSettings,
+    endpoints: impl Fn((f32,f32,f32,f32)) -> (Vec3, Vec3, Color),
+) -> Vec<(Vec3, Vec3, Color)> {
+    let axes = channel_axes(settings);
+    let alpha_values = alpha_values(settings);
+    let mut arrows = Vec::new();
+
+    for (layer, &alpha) in alpha_values.iter().enumerate() {
+        let layer_offset = settings.alpha_layout.offset(layer, alpha_values.len());
+        for index_of_a in 0..axes[0].len() {
+            for index_of_b in 0..axes[1].len() {
+                for index_of_c in 0..axes[2].len() {
+                    let (from, to, color) = endpoints((axes[0].value(index_of_a), axes[1].value(index_of_b), axes[2].value(index_of_c), alpha));
+                    arrows.push((from + layer_offset, to + layer_offset, color));
+                }
+            }
+        }
+    }
+    arrows
+}
+
+fn spawn_arrows(
+    commands: &mut Commands,
+    gizmo_assets: &mut Assets<GizmoAsset>,
+    settings: &VisualizationSettings,
+    target: &VisualizationTarget,
+    arrows: &[(Vec3, Vec3, Color)],
+) {
+    let scale = SCALE * settings.viz_scale;
+    let mut gizmo = GizmoAsset::new();
+    for (from, to, color) in arrows {
+        gizmo.arrow(*from * scale, *to * scale, *color);
+    }
+
+    commands.spawn((
+        Gizmo {
+            handle: gizmo_assets.add(gizmo),
+            line_config: GizmoLineConfig {
+                width: settings.line_width * 2.,
+                ..default()
+            },
+            ..default()
+        },
+        target.bundle(),
+    ));
+}
+
+// Sampled values along one channel and whether its ends join into a ring
+pub struct ChannelAxis {
+    values: Vec<ChannelIndex>,
+    // Values of the color steps, kept when the samples follow a separate mesh resolution
+    color_steps: Option<Vec<f32>>,
+    closed: bool,
+}
+
+impl ChannelAxis {
+    // Sampled at the color steps
+    pub fn new(channel: &ColorChannel, info: &ChannelInfo, not_vertex: bool) -> Self {
+        // Only a cyclic channel sweeping its full range closes on itself
+        let closed = info.cyclic && channel.covers(info.span());
+        Self {
+            values: channel.generate(not_vertex, closed),
+            color_steps: None,
+            closed,
+        }
+    }
+
+    // Sampled at the mesh resolution, remembering the color steps to band the colors by
+    pub fn mesh(channel: &ColorChannel, info: &ChannelInfo, not_vertex: bool) -> Self {
+        let closed = info.cyclic && channel.covers(info.span());
+        Self {
+            values: channel.generate_mesh(not_vertex, closed),
+            color_steps: channel.mesh_steps.map(|_| channel.generate(not_vertex, closed).iter().map(|step| step.value).collect()),
+            closed,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn value(&self, index: usize) -> f32 {
+        self.values[index].value
+    }
+
+    // The color step whose band the sample falls in, the sample itself while mesh and color steps coincide
+    pub fn color_value(&self, index: usize) -> f32 {
+        let value = self.value(index);
+        let Some(steps) = &self.color_steps else {return value};
+        let (Some(first), Some(last)) = (steps.first(), steps.last()) else {return value};
+        // Bands run from each step up to the next one in the sweep direction
+        let before = |step: f32| if last >= first {step <= value + 1e-5} else {step >= value - 1e-5};
+        steps.iter().rev().copied().find(|step| before(*step)).unwrap_or(*first)
+    }
+
+    pub fn has_color_steps(&self) -> bool {
+        self.color_steps.is_some()
+    }
+
+    // Index of the sample `offset` steps along, None once a linear axis runs out
+    pub fn neighbor(&self, index: usize, offset: usize) -> Option<usize> {
+        let raw = index + offset;
+        if offset == 0 {
+            Some(index)
+        } else if self.closed {
+            if self.len() > 1 { Some(raw % self.len()) } else { None }
+        } else if raw < self.len() {
+            Some(raw)
+        } else {
+            None
+        }
+    }
+
+    // Index of the sample one step back, None before the start of a linear axis
+    fn previous(&self, index: usize) -> Option<usize> {
+        if self.closed {
+            if self.len() > 1 { Some((index + self.len() - 1) % self.len()) } else { None }
+        } else {
+            index.checked_sub(1)
+        }
+    }
+
+    // Whether a cap of the volume shell sits at this index
+    fn is_boundary(&self, index: usize) -> bool {
+        !self.closed && (index == 0 || index == self.len() - 1)
+    }
+}
+
+// The sampled lattice of the three model channels, at their color steps
+pub fn channel_axes(settings: &VisualizationSettings) -> [ChannelAxis; 3] {
+    let not_vertex = settings.dimensionality != Dimensionality::Vertex;
+    let channels = settings.model_channels();
+
+    [
+        ChannelAxis::new(&settings.channel_settings.0, &channels[0], not_vertex),
+        ChannelAxis::new(&settings.channel_settings.1, &channels[1], not_vertex),
+        ChannelAxis::new(&settings.channel_settings.2, &channels[2], not_vertex),
+    ]
+}
+
+// The lattice the shapes are built on, at each channel's mesh resolution.
+// Points and lines are the samples themselves, so they stay at the color steps.
+pub fn mesh_axes(settings: &VisualizationSettings) -> [ChannelAxis; 3] {
+    if !matches!(settings.dimensionality, Dimensionality::Face | Dimensionality::Volume) {
+        return channel_axes(settings);
+    }
+    let channels = settings.model_channels();
+
+    [
+        ChannelAxis::mesh(&settings.channel_settings.0, &channels[0], true),
+        ChannelAxis::mesh(&settings.channel_settings.1, &channels[1], true),
+        ChannelAxis::mesh(&settings.channel_settings.2, &channels[2], true),
+    ]
+}
+
+// Alpha is swept as independent layers rather than a lattice axis
+pub fn alpha_values(settings: &VisualizationSettings) -> Vec<f32> {
+    if settings.sweep_alpha {
+        settings.alpha_channel.generate(false, false).iter().map(|alpha| alpha.value).collect()
+    }
+    else {
+        vec![settings.visualization_alpha]
+    }
+}
 
 fn generate_dimension_lists(settings: &VisualizationSettings) ->  DimensionList{
 
-    let yuv_offset = if settings.color_model.is_luma_chroma() {-0.5} else {0.};
-    
     let mut dim_list: DimensionList = 
     match settings.dimensionality {
         Dimensionality::Vertex => DimensionList::Vertex(VertexList::new()),
@@ -561,130 +978,149 @@ fn generate_dimension_lists(settings: &VisualizationSettings) ->  DimensionList{
         Dimensionality::Volume => DimensionList::Volume(FaceList::new()),
     };
 
+    let axes = mesh_axes(settings);
+    let channels = settings.model_channels();
+    // Colors follow the color steps rather than the mesh under discrete color
+    let banded = settings.discrete_color && axes.iter().any(ChannelAxis::has_color_steps);
+    let alpha_values = alpha_values(settings);
+
+    for (layer, &alpha) in alpha_values.iter().enumerate() {
+        let layer_offset = settings.alpha_layout.offset(layer, alpha_values.len());
+
+        // Lattice index offset from `index`, None if it falls off the end of a linear axis
+        let step = |index: [usize; 3], offset: [usize; 3]| -> Option<[usize; 3]> {
+            Some([
+                axes[0].neighbor(index[0], offset[0])?,
+                axes[1].neighbor(index[1], offset[1])?,
+                axes[2].neighbor(index[2], offset[2])?,
+            ])
+        };
+
+        let evaluate = |index: [usize; 3]| -> ([f32; 3], P_Color) {
+            let (point, color) = get_point_and_color((axes[0].value(index[0]), axes[1].value(index[1]), axes[2].value(index[2]), alpha), settings);
+            if !banded {return (point, color)};
+            let (_, color) = get_point_and_color((axes[0].color_value(index[0]), axes[1].color_value(index[1]), axes[2].color_value(index[2]), alpha), settings);
+            (point, color)
+        };
+
+        // Distance to the next sample along the slicing axis, or the previous one at the end of the axis
+        let delta_e = |index: [usize; 3], color: P_Color| -> Option<f32> {
+            let direction = settings.face_slicing.get_edge_offsets()[1];
+            let axis = direction.iter().position(|offset| *offset == 1)?;
+            let neighbor = step(index, direction).or_else(|| {
+                let mut previous = index;
+                previous[axis] = axes[axis].previous(index[axis])?;
+                Some(previous)
+            })?;
+            // Measured between the colors as seen through the CVD filter
+            let rgb = |color: P_Color| {
+                let [r, g, b, _] = color.to_rgb().to_array();
+                settings.cvd.simulate_srgb([r, g, b])
+            };
+            Some(settings.delta_e_metric.delta_e(rgb(color), rgb(evaluate(neighbor).1)))
+        };
+
+        let sample = |index: [usize; 3], offset: [usize; 3]| -> Option<VertexObject> {
+            let index = step(index, offset)?;
+            let (point, color) = evaluate(index);
+            if settings.contrast.hide_failing {
+                let [r, g, b, _] = color.to_rgb().to_array();
+                if !settings.contrast.passes([r, g, b]) {return None};
+            }
+            let delta_e = if settings.coloring == ColoringMode::DeltaE {delta_e(index, color)} else {None};
+            Some(VertexObject::new((Vec3::from(point) + layer_offset).into(), color, delta_e))
+        };
+
+        let quad = |index: [usize; 3], offsets: [[usize; 3]; 4]| -> Option<[VertexObject; 4]> {
+            Some([
+                sample(index, offsets[0])?,
+                sample(index, offsets[1])?,
+                sample(index, offsets[2])?,
+                sample(index, offsets[3])?,
+            ])
+        };
+
+        // Channel values `offset` along from `index`, carried past the end of a closed axis instead of wrapping
+        let unwrapped_channels = |index: [usize; 3], offset: [usize; 3]| -> Option<[f32; 3]> {
+            let neighbor = step(index, offset)?;
+            Some([0, 1, 2].map(|axis| {
+                let value = axes[axis].value(neighbor[axis]);
+                if neighbor[axis] < index[axis] {value + channels[axis].span()} else {value}
+            }))
+        };
+
+        // Whether a cap quad's winding points its normal towards the samples one step inside the volume
+        let cap_faces_inward = |index: [usize; 3], axis: usize, offsets: [[usize; 3]; 4]| -> bool {
+            let point = |index: [usize; 3]| Vec3::from(evaluate(index).0);
+            let (Some(i1), Some(i2), Some(i3)) = (step(index, offsets[0]), step(index, offsets[1]), step(index, offsets[2])) else {return false};
+            let mut inner = index;
+            inner[axis] = if index[axis] == 0 {axes[axis].neighbor(0, 1)} else {axes[axis].previous(index[axis])}.unwrap_or(index[axis]);
+            let (p1, p2, p3) = (point(i1), point(i2), point(i3));
+            (p2 - p1).cross(p3 - p1).dot(point(inner) - p1) > 0.
+        };
+
+        let mut edge_subdivisions = EdgeSubdivisions::new();
+        let add_quad = |face_list: &mut FaceList, edge_subdivisions: &mut EdgeSubdivisions, index: [usize; 3], offsets: [[usize; 3]; 4]| {
+            let Some(vertices) = quad(index, offsets) else {return};
+            if !settings.adaptive_subdivision {
+                let [v1, v2, v3, v4] = vertices;
+                face_list.add_quad(v1, v2, v3, v4);
+                return;
+            }
 
-    let channel_a_list = settings.channel_settings.0.generate(settings.dimensionality != Dimensionality::Vertex);
-    let channel_b_list = settings.channel_settings.1.generate(settings.dimensionality != Dimensionality::Vertex);
-    let channel_c_list = settings.channel_settings.2.generate(settings.dimensionality != Dimensionality::Vertex);
-
-    for (index_of_a, a) in channel_a_list.iter().enumerate() {
-        if settings.dimensionality != Dimensionality::Vertex && channel_a_list.len() - index_of_a <= 1 {continue};
-        for (index_of_b, b ) in channel_b_list.iter().enumerate() {
-            if settings.dimensionality != Dimensionality::Vertex && channel_b_list.len()  - index_of_b <= 1 {continue};
-            for (index_of_c, c ) in channel_c_list.iter().enumerate() {
-                if settings.dimensionality != Dimensionality::Vertex && channel_c_list.len()  - index_of_c <= 1 {continue};
-                    // Generate points of the dimension
-                    let color_point_1 = (
-                        a.value,
-                        b.value + yuv_offset,
-                        c.value + yuv_offset,
-                    );
-                    let point_1 = VertexObject::from_tuple(get_point_and_color(color_point_1, settings));
+            let corners = offsets.map(|offset| unwrapped_channels(index, offset).unwrap());
+            let delta_es = vertices.each_ref().map(|vertex| vertex.delta_e.map(|delta_e| delta_e.into_inner()));
+            // Any point of the lattice quad, bilinear in channel space between its corners
+            let at = |uv: Vec2| -> VertexObject {
+                let weights = [(1. - uv.x) * (1. - uv.y), uv.x * (1. - uv.y), uv.x * uv.y, (1. - uv.x) * uv.y];
+                let blend = |values: [f32; 4]| values.iter().zip(weights).map(|(value, weight)| value * weight).sum::<f32>();
+                let values = [0, 1, 2].map(|axis| {
+                    let value = blend(corners.map(|corner| corner[axis]));
+                    let info = channels[axis];
+                    if info.cyclic {info.range.0 + (value - info.range.0).rem_euclid(info.span())} else {value}
+                });
+                let (point, color) = get_point_and_color((values[0], values[1], values[2], alpha), settings);
+                let delta_e = match delta_es {
+                    [Some(d1), Some(d2), Some(d3), Some(d4)] => Some(blend([d1, d2, d3, d4])),
+                    _ => None,
+                };
+                VertexObject::new((Vec3::from(point) + layer_offset).into(), color, delta_e)
+            };
+            let lattice = offsets.map(|offset| step(index, offset).unwrap());
+            tessellate_quad(face_list, edge_subdivisions, &at, lattice, vertices, settings.subdivision_depth, settings.subdivision_tolerance);
+        };
+
+        for index_of_a in 0..axes[0].len() {
+            for index_of_b in 0..axes[1].len() {
+                for index_of_c in 0..axes[2].len() {
+                    let index = [index_of_a, index_of_b, index_of_c];
                     match &mut dim_list {
                         DimensionList::Vertex(vertex_list) => {
-                            vertex_list.add_vertex(&point_1);
+                            if let Some(point) = sample(index, [0, 0, 0]) {
+                                vertex_list.add_vertex(&point);
+                            }
                         },
                         DimensionList::Edge(edge_list) => {
                             let slice_offset = settings.face_slicing.get_edge_offsets();
-                            // let (a_end, b_end,c_end) = (index_of_a == channel_a_list.len() - 1, index_of_b == channel_b_list.len() - 1,index_of_c == channel_c_list.len() - 1);
-                            let (a2,b2,c2) = (
-                                channel_a_list.get(wrap_index(index_of_a, slice_offset[1][0], channel_a_list.len())).unwrap().value,
-                                channel_b_list.get(wrap_index(index_of_b, slice_offset[1][1], channel_b_list.len())).unwrap().value + yuv_offset,
-                                channel_c_list.get(wrap_index(index_of_c, slice_offset[1][2], channel_c_list.len())).unwrap().value + yuv_offset,
-                            );
-                            let color_point_2: (f32,f32,f32) = (
-                                a2,
-                                b2,
-                                c2,
-                            );
-                            let point_2 = VertexObject::from_tuple(get_point_and_color(color_point_2, settings));
-                            edge_list.add_edge(point_1, point_2);
+                            if let (Some(point_1), Some(point_2)) = (sample(index, slice_offset[0]), sample(index, slice_offset[1])) {
+                                edge_list.add_edge(point_1, point_2);
+                            }
                         },
                         DimensionList::Face(face_list) => {
-                            let slice_offset = settings.face_slicing.get_face_offsets();
-                            let (a2,b2,c2) = (
-                                channel_a_list.get(wrap_index(index_of_a, slice_offset[1][0], channel_a_list.len())).unwrap().value,
-                                channel_b_list.get(wrap_index(index_of_b, slice_offset[1][1], channel_b_list.len())).unwrap().value + yuv_offset,
-                                channel_c_list.get(wrap_index(index_of_c, slice_offset[1][2], channel_c_list.len())).unwrap().value + yuv_offset,
-                            );
-                            let color_point_2: (f32,f32,f32) = (
-                                a2,
-                                b2,
-                                c2,
-                            );
-                            let point_2 = VertexObject::from_tuple(get_point_and_color(color_point_2, settings));
-
-                            let (a3,b3,c3) = (
-                                channel_a_list.get(wrap_index(index_of_a, slice_offset[2][0], channel_a_list.len())).unwrap().value,
-                                channel_b_list.get(wrap_index(index_of_b, slice_offset[2][1], channel_b_list.len())).unwrap().value + yuv_offset,
-                                channel_c_list.get(wrap_index(index_of_c, slice_offset[2][2], channel_c_list.len())).unwrap().value + yuv_offset,
-                            );
-                            let color_point_3: (f32,f32,f32) = (
-                                a3,
-                                b3,
-                                c3,
-                            );
-                            let point_3 = VertexObject::from_tuple(get_point_and_color(color_point_3, settings));
-
-                            let (a4,b4,c4) = (
-                                channel_a_list.get(wrap_index(index_of_a, slice_offset[3][0], channel_a_list.len())).unwrap().value,
-                                channel_b_list.get(wrap_index(index_of_b, slice_offset[3][1], channel_b_list.len())).unwrap().value + yuv_offset,
-                                channel_c_list.get(wrap_index(index_of_c, slice_offset[3][2], channel_c_list.len())).unwrap().value + yuv_offset,
-                            );
-                            let color_point_4: (f32,f32,f32) = (
-                                a4,
-                                b4,
-                                c4,
-                            );
-                            let point_4 = VertexObject::from_tuple(get_point_and_color(color_point_4, settings));
-                            
-                            face_list.add_quad(point_1, point_2, point_3, point_4);
+                            add_quad(face_list, &mut edge_subdivisions, index, settings.face_slicing.get_face_offsets());
                         },
                         DimensionList::Volume(face_list) => {
-                            // Check each axis separately
-                            // X-min and X-max
-                            if index_of_a == 0 || index_of_a == channel_a_list.len() - 2 {
-                                let slice = SlicingMethod::X;
+                            // Cap each linear axis at its first and last sample
+                            for (axis, slice) in [SlicingMethod::X, SlicingMethod::Y, SlicingMethod::Z].iter().enumerate() {
+                                if !axes[axis].is_boundary(index[axis]) {continue};
                                 let offsets = slice.get_face_offsets();
-                                let mut verts = Vec::new();
-                                for o in &offsets {
-                                    let ca = channel_a_list.get(wrap_index(index_of_a, o[0], channel_a_list.len())).unwrap().value;
-                                    let cb = channel_b_list.get(wrap_index(index_of_b, o[1], channel_b_list.len())).unwrap().value + yuv_offset;
-                                    let cc = channel_c_list.get(wrap_index(index_of_c, o[2], channel_c_list.len())).unwrap().value + yuv_offset;
-                                    verts.push(VertexObject::from_tuple(get_point_and_color((ca,cb,cc), settings)));
-                                }
-                                face_list.add_quad(verts[0].clone(), verts[1].clone(), verts[2].clone(), verts[3].clone());
-                            }
-
-                            // Y-min and Y-max
-                            if index_of_b == 0 || index_of_b == channel_b_list.len() - 2 {
-                                let slice = SlicingMethod::Y;
-                                let offsets = slice.get_face_offsets();
-                                let mut verts = Vec::new();
-                                for o in &offsets {
-                                    let ca = channel_a_list.get(wrap_index(index_of_a, o[0], channel_a_list.len())).unwrap().value;
-                                    let cb = channel_b_list.get(wrap_index(index_of_b, o[1], channel_b_list.len())).unwrap().value + yuv_offset;
-                                    let cc = channel_c_list.get(wrap_index(index_of_c, o[2], channel_c_list.len())).unwrap().value + yuv_offset;
-                                    verts.push(VertexObject::from_tuple(get_point_and_color((ca,cb,cc), settings)));
-                                }
-                                face_list.add_quad(verts[0].clone(), verts[1].clone(), verts[2].clone(), verts[3].clone());
-                            }
-
-                            // Z-min and Z-max
-                            if index_of_c == 0 || index_of_c == channel_c_list.len() - 2 {
-                                let slice = SlicingMethod::Z;
-                                let offsets = slice.get_face_offsets();
-                                let mut verts = Vec::new();
-                                for o in &offsets {
-                                    let ca = channel_a_list.get(wrap_index(index_of_a, o[0], channel_a_list.len())).unwrap().value;
-                                    let cb = channel_b_list.get(wrap_index(index_of_b, o[1], channel_b_list.len())).unwrap().value + yuv_offset;
-                                    let cc = channel_c_list.get(wrap_index(index_of_c, o[2], channel_c_list.len())).unwrap().value + yuv_offset;
-                                    verts.push(VertexObject::from_tuple(get_point_and_color((ca,cb,cc), settings)));
-                                }
-                                face_list.add_quad(verts[0].clone(), verts[1].clone(), verts[2].clone(), verts[3].clone());
+                                // Opposite caps share offsets, so flip the ones facing into the volume
+                                let offsets = if cap_faces_inward(index, axis, offsets) {[offsets[0], offsets[3], offsets[2], offsets[1]]} else {offsets};
+                                add_quad(face_list, &mut edge_subdivisions, index, offsets);
                             }
                         },
                     }
-                
+                }
             }
         }
     }
@@ -692,7 +1128,127 @@ fn generate_dimension_lists(settings: &VisualizationSettings) ->  DimensionList{
     dim_list
 }
 
-fn get_point_and_color(base_color: (f32,f32,f32), settings: &VisualizationSettings) -> ([f32;3], P_Color){
+// Surface points along each subdivided lattice edge, keyed by its two lattice indices in ascending order and
+// listed from the first to the second, so both quads sharing the edge build their sides from the same points
+type EdgeSubdivisions = HashMap<([usize; 3], [usize; 3]), Vec<VertexObject>>;
+
+// Tessellates a lattice quad on a uniform (u, v) grid, evaluated by `at`, fine enough that no cell center strays further
+// than `tolerance` from its corners' average and that each side takes every point of its shared edge subdivision.
+// Grid points a side has beyond its edge's own points lie on the edge's straight segments, so neighbors meet without cracks.
+fn tessellate_quad(
+    face_list: &mut FaceList,
+    edge_subdivisions: &mut EdgeSubdivisions,
+    at: &dyn Fn(Vec2) -> VertexObject,
+    lattice: [[usize; 3]; 4],
+    vertices: [VertexObject; 4],
+    max_depth: usize,
+    tolerance: f32,
+) {
+    let finest = 1u32 << max_depth;
+    let corner_cells = [[0, 0], [finest, 0], [finest, finest], [0, finest]];
+
+    // Every point evaluated once, keyed by its grid position at the finest level
+    let mut evaluated: HashMap<[u32; 2], VertexObject> = HashMap::new();
+    for (corner, cell) in corner_cells.into_iter().enumerate() {
+        evaluated.insert(cell, vertices[corner].clone());
+    }
+    let mut point_at = |cell: [u32; 2]| -> VertexObject {
+        evaluated.entry(cell).or_insert_with(|| at(Vec2::new(cell[0] as f32, cell[1] as f32) / finest as f32)).clone()
+    };
+
+    // Grid position of point `k` of `count` segments along a side, walking from its corner to the next
+    let side_cell = |side: usize, k: u32, count: u32| -> [u32; 2] {
+        let t = k * (finest / count);
+        match side {
+            0 => [t, 0],
+            1 => [finest, t],
+            2 => [finest - t, finest],
+            _ => [0, finest - t],
+        }
+    };
+
+    // Each side's edge points in ascending lattice order, and whether the side runs that way
+    let mut sides: Vec<(Vec<VertexObject>, bool)> = Vec::with_capacity(4);
+    for side in 0..4 {
+        let (first, second) = (lattice[side], lattice[(side + 1) % 4]);
+        let forward = first < second;
+        let key = if forward {(first, second)} else {(second, first)};
+        let points = edge_subdivisions.entry(key).or_insert_with(|| {
+            // Halve every segment while any segment's midpoint bows away from its chord
+            let mut points = vec![vertices[side].clone(), vertices[(side + 1) % 4].clone()];
+            for depth in 0..max_depth {
+                let count = 1 << (depth + 1);
+                let midpoints: Vec<VertexObject> = (0..points.len() as u32 - 1).map(|k| point_at(side_cell(side, 2 * k + 1, count))).collect();
+                let bowed = midpoints.iter().enumerate().any(|(k, midpoint)| {
+                    midpoint.point.into_vec3().distance((points[k].point.into_vec3() + points[k + 1].point.into_vec3()) / 2.) > tolerance
+                });
+                if !bowed {break};
+                let mut refined = Vec::with_capacity(points.len() + midpoints.len());
+                for (k, point) in points.into_iter().enumerate() {
+                    if k > 0 {refined.push(midpoints[k - 1].clone())};
+                    refined.push(point);
+                }
+                points = refined;
+            }
+            if !forward {points.reverse()};
+            points
+        });
+        sides.push((points.clone(), forward));
+    }
+
+    // Deep enough for every side, then deeper while any cell center bows away from its corners
+    let mut depth = sides.iter().map(|(points, _)| (points.len() - 1).trailing_zeros() as usize).max().unwrap_or(0);
+    while depth < max_depth {
+        let count = 1u32 << depth;
+        let cell = finest / count;
+        let bowed = (0..count).any(|i| (0..count).any(|j| {
+            let corners = [[i, j], [i + 1, j], [i + 1, j + 1], [i, j + 1]].map(|[a, b]| point_at([a * cell, b * cell]).point.into_vec3());
+            let center = point_at([i * cell + cell / 2, j * cell + cell / 2]).point.into_vec3();
+            center.distance(corners.iter().sum::<Vec3>() / 4.) > tolerance
+        }));
+        if !bowed {break};
+        depth += 1;
+    }
+
+    let count = 1u32 << depth;
+    let cell = finest / count;
+    let mut grid_point = |i: u32, j: u32| -> VertexObject {
+        let (side, k) = match (i, j) {
+            (_, 0) => (0, i),
+            (i, _) if i == count => (1, j),
+            (_, j) if j == count => (2, count - i),
+            (0, _) => (3, count - j),
+            _ => return point_at([i * cell, j * cell]),
+        };
+        let (points, forward) = &sides[side];
+        // Position along the edge in its own direction, in its own segments
+        let k = if *forward {k} else {count - k};
+        let ratio = count / (points.len() as u32 - 1);
+        let (segment, remainder) = ((k / ratio) as usize, k % ratio);
+        if remainder == 0 {
+            points[segment].clone()
+        } else {
+            points[segment].lerp(&points[segment + 1], remainder as f32 / ratio as f32)
+        }
+    };
+
+    for i in 0..count {
+        for j in 0..count {
+            let [v1, v2, v3, v4] = [grid_point(i, j), grid_point(i + 1, j), grid_point(i + 1, j + 1), grid_point(i, j + 1)];
+            face_list.add_quad_colored_by(v1, v2, v3, v4, &vertices[0]);
+        }
+    }
+}
+
+// A lattice sample at each stage of the color pipeline
+pub struct SampleColors {
+    // Straight from the channel values
+    pub raw: P_Color,
+    // After the perceptual offset and gamma, before any LUT
+    pub transformed: P_Color,
+}
+
+pub fn sample_colors(base_color: (f32,f32,f32,f32), settings: &VisualizationSettings) -> SampleColors {
     let (r_gamma,g_gamma,b_gamma) = if settings.gamma_deform {(1.,1.,1.)} else {settings.gamma};
     let gamma_adjust = 2.2;
     let gamma = [
@@ -701,8 +1257,18 @@ fn get_point_and_color(base_color: (f32,f32,f32), settings: &VisualizationSettin
         (b_gamma/gamma_adjust) as f32,
     ];
     
-    let base_color = (base_color.0,base_color.1,base_color.2,settings.visualization_alpha);
-    let raw_color = P_Color::from_tuple(base_color, settings.color_model);
+    let raw_color = match settings.active_custom_model() {
+        Some(model) => {
+            let [r, g, b] = model.to_linear_rgb([base_color.0, base_color.1, base_color.2]).map(linear_to_srgb);
+            P_Color::from_tuple((r, g, b, base_color.3), ColorModel::RGBA)
+        },
+        None if settings.extended_range.reads_linear_light() => {
+            let [r, g, b, a] = P_Color::from_tuple(base_color, settings.color_model).to_rgb().to_array();
+            let [r, g, b] = settings.extended_range.encode_output([r, g, b]);
+            P_Color::from_tuple((r, g, b, a), ColorModel::RGBA)
+        },
+        None => P_Color::from_tuple(base_color, settings.color_model),
+    };
     let chroma = base_color.1;
 
     let color: P_Color = 
@@ -719,14 +1285,59 @@ fn get_point_and_color(base_color: (f32,f32,f32), settings: &VisualizationSettin
             gamma[2],
         );
 
-    let base_color = if settings.gamma_deform {color} else {raw_color};
-    
-    let point: Vec3 = {
-        let point = base_color.convert_color(settings.color_space_model).from_space_to_space(settings.color_space, ColorSpace::XYZ);
-        let point = if settings.model_mirrored {point.mirror_colorspace()} else {point};
-        let (x,y,z, _) = point.to_tuple(); 
-        Vec3 {x, y, z}
-    };
+    SampleColors { raw: raw_color, transformed: color }
+}
+
+pub fn get_point_and_color(base_color: (f32,f32,f32,f32), settings: &VisualizationSettings) -> ([f32;3], P_Color){
+    let colors = sample_colors(base_color, settings);
+    let color = settings.lut.apply(colors.transformed);
+    let point = position_color(if settings.gamma_deform {colors.transformed} else {colors.raw}, settings);
 
     (point.into(), color)
 }
+
+// Where a pipeline color is placed: through the LUT, then as seen through the CVD filter when positions follow it
+pub fn position_color(color: P_Color, settings: &VisualizationSettings) -> Vec3 {
+    let color = settings.lut.apply(color);
+    let color = if settings.cvd.affect_positions && settings.cvd.is_active() {settings.cvd.simulate_color(color)} else {color};
+    place_color(color, settings)
+}
+
+// Where a sample sits without and with the LUT, and the color it is drawn with
+pub fn lut_displacement(base_color: (f32,f32,f32,f32), settings: &VisualizationSettings) -> (Vec3, Vec3, P_Color) {
+    let colors = sample_colors(base_color, settings);
+    let original = if settings.gamma_deform {colors.transformed} else {colors.raw};
+    let (point, color) = get_point_and_color(base_color, settings);
+    (place_color(original, settings), Vec3::from(point), color)
+}
+
+
+// Model space position of a color in the selected color space model
+pub fn place_color(color: P_Color, settings: &VisualizationSettings) -> Vec3 {
+    let [r, g, b, _] = color.to_rgb().to_array();
+    let cylindrical = settings.color_space == ColorSpace::Cylindrical;
+    match (settings.position_space, &settings.custom_model) {
+        (Some(PositionSpace::Perceptual(space)), _) => return space.place(space.coordinates([r, g, b]), cylindrical),
+        (Some(PositionSpace::Custom), Some(model)) => {
+            let channels = model.from_linear_rgb([r, g, b].map(srgb_to_linear));
+            return place_channels(&model.channels(), channels, cylindrical);
+        },
+        _ => {},
+    }
+
+    let point = color.convert_color(settings.color_space_model).from_space_to_space(settings.color_space, ColorSpace::XYZ);
+    let point = if settings.model_mirrored {point.mirror_colorspace()} else {point};
+    let (x,y,z, _) = point.to_tuple(); 
+    Vec3 {x, y, z}
+}
+
+// Channel values normalized into the unit cube, rolled into a cylinder around its center when the first channel is a hue
+pub fn place_channels(channels: &[ChannelInfo; 3], values: [f32; 3], cylindrical: bool) -> Vec3 {
+    let [x, y, z] = [0, 1, 2].map(|axis| (values[axis] - channels[axis].range.0) / channels[axis].span());
+    if cylindrical && channels[0].cyclic {
+        let angle = x * std::f32::consts::TAU;
+        Vec3::new(0.5 + 0.5 * y * angle.cos(), 0.5 + 0.5 * y * angle.sin(), z)
+    } else {
+        Vec3::new(x, y, z)
+    }
+}